@@ -1,5 +1,6 @@
 #![feature(const_slice_index)]
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 //! A simple to use library for parsing and creating Nintendo SARC files in Rust.
 //! Uses zero allocation parsing and handles file alignment requirements for common
 //! formats and games like `The Legend of Zelda: Breath of the Wild`.
@@ -17,27 +18,168 @@
 //!     println!("File size: {}", file.data.len()); // Print data size
 //! }
 //! ```
+//!
+//! The `std` feature is enabled by default. Without it, the crate only depends
+//! on `alloc` and provides the reader, minus the file system helpers.
+extern crate alloc;
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    string::String,
+    vec::Vec,
+};
 use binread::BinRead;
+#[cfg(feature = "std")]
 use binwrite::BinWrite;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "bytes")]
+mod owned;
 mod parse;
+#[cfg(feature = "test-utils")]
+mod test_utils;
+#[cfg(feature = "std")]
 mod writer;
-pub use parse::Sarc;
-pub use writer::SarcWriter;
+mod yaz0;
+#[cfg(feature = "mmap")]
+pub use mmap::SarcMmap;
+#[cfg(feature = "bytes")]
+pub use owned::OwnedSarc;
+pub use parse::{FileInfo, FileIter, NameOffsetMode, Sarc, SarcDiff, SarcStats};
+#[cfg(feature = "test-utils")]
+pub use test_utils::{verify_roundtrip, RoundtripError};
+#[cfg(feature = "std")]
+pub use writer::{MergePolicy, SarcWriter};
+pub use yaz0::CompressionLevel;
 
 /// A file that is stored in a SARC archive.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub struct File<'a> {
     /// File name. May be empty for file entries that do not use the file name
     /// table.
     pub name: Option<&'a str>,
+    /// Name hash stored in the file table. This is the only identifier of
+    /// nameless entries.
+    pub name_hash: u32,
+    /// File data (as a slice).
+    pub data: &'a [u8],
+}
+
+impl<'a> File<'a> {
+    /// Returns the extension of the file name (without the dot), if the file
+    /// is named and its name has one
+    pub fn extension(&self) -> Option<&'a str> {
+        self.name.and_then(|name| split_file_name(name).1)
+    }
+
+    /// Returns the file name without its directory and extension, if the file
+    /// is named
+    pub fn stem(&self) -> Option<&'a str> {
+        self.name.map(|name| split_file_name(name).0)
+    }
+}
+
+/// Files are equal if they have the same name and data. The stored name hash
+/// depends on the archive's hash multiplier, so it is only compared for
+/// nameless files, which it identifies.
+impl PartialEq for File<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.data == other.data
+            && (self.name.is_some() || self.name_hash == other.name_hash)
+    }
+}
+
+impl Eq for File<'_> {}
+
+impl AsRef<[u8]> for File<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl core::ops::Deref for File<'_> {
+    type Target = [u8];
+
+    /// Dereferences to the file data
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+/// A file that is stored in a SARC archive, with a name that may contain
+/// replacement characters for invalid UTF-8. See [`Sarc::file_at_lossy`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct LossyFile<'a> {
+    /// File name. May be empty for file entries that do not use the file name
+    /// table.
+    pub name: Option<Cow<'a, str>>,
+    /// Name hash stored in the file table.
+    pub name_hash: u32,
     /// File data (as a slice).
     pub data: &'a [u8],
 }
 
+/// An owned file entry, e.g. for serializing the contents of an archive.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileEntry {
+    /// File name. May be empty for file entries that do not use the file name
+    /// table.
+    pub name: Option<String>,
+    /// File data.
+    pub data: Vec<u8>,
+}
+
+impl From<File<'_>> for FileEntry {
+    fn from(file: File<'_>) -> Self {
+        FileEntry {
+            name: file.name.map(|name| name.to_owned()),
+            data: file.data.to_vec(),
+        }
+    }
+}
+
+/// Splits the last component of an archive file name into its stem and
+/// extension
+fn split_file_name(name: &str) -> (&str, Option<&str>) {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    match file_name.rfind('.') {
+        Some(idx) => (&file_name[..idx], Some(&file_name[idx + 1..])),
+        None => (file_name, None),
+    }
+}
+
+/// Checks if a data slice represents a SARC archive, which may be Yaz0
+/// compressed
+fn is_file_sarc(data: &[u8]) -> bool {
+    data.len() >= 0x20
+        && (&data[0..4] == b"SARC" || (&data[0..4] == b"Yaz0" && &data[0x11..0x15] == b"SARC"))
+}
+
 const SARC_MAGIC: [char; 4] = ['S', 'A', 'R', 'C'];
 const SFAT_MAGIC: [char; 4] = ['S', 'F', 'A', 'T'];
 const SFNT_MAGIC: [char; 4] = ['S', 'F', 'N', 'T'];
+/// The only SARC version known to be used by official archives
+const SARC_VERSION: u16 = 0x0100;
 
+/// Name offsets in SFAT entries are stored divided by this, so names have to
+/// be aligned to (a multiple of) it
+const NAME_OFFSET_SCALE: usize = 4;
+
+/// The largest file count a SARC archive can have, since the top bits of the
+/// 16-bit count are reserved
+const MAX_FILE_COUNT: usize = 0x3FFF;
+
+/// The largest (scaled) name offset that fits below the flag in SFAT entries
+const MAX_NAME_OFFSET: usize = 0xFFFFFF;
+
+/// The name hash multiplier used by virtually all official archives
+const DEFAULT_HASH_MULTIPLIER: u32 = 0x65;
+
+/// Hashes a file name like official archives do. This iterates the UTF-8
+/// bytes of the name, not its code points, so no decoding is involved and
+/// ASCII names need no separate fast path.
 const fn hash_name(multiplier: u32, name: &str) -> u32 {
     let mut hash = 0u32;
     let bytes = name.as_bytes();
@@ -54,7 +196,8 @@ const fn hash_name(multiplier: u32, name: &str) -> u32 {
     hash
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, BinRead)]
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone, BinRead)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[br(repr = u16)]
 #[repr(u16)]
 /// An enum to represent SARC endianness
@@ -65,8 +208,59 @@ pub enum Endian {
     Little = 0xFEFF,
 }
 
+impl Endian {
+    /// Returns the endianness of the target platform
+    pub const fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+
+    /// Returns the other endianness
+    pub const fn opposite(self) -> Self {
+        match self {
+            Endian::Big => Endian::Little,
+            Endian::Little => Endian::Big,
+        }
+    }
+}
+
+/// Formats the endianness as “Big” or “Little”
+impl core::fmt::Display for Endian {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Endian::Big => f.write_str("Big"),
+            Endian::Little => f.write_str("Little"),
+        }
+    }
+}
+
+/// Parses “big”, “little”, “be” or “le”, ignoring case, so the output of
+/// `Display` can be parsed back
+impl core::str::FromStr for Endian {
+    type Err = ParseEndianError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("big") || s.eq_ignore_ascii_case("be") {
+            Ok(Endian::Big)
+        } else if s.eq_ignore_ascii_case("little") || s.eq_ignore_ascii_case("le") {
+            Ok(Endian::Little)
+        } else {
+            Err(ParseEndianError(s.to_owned()))
+        }
+    }
+}
+
+/// The error returned when a string is not a valid [`Endian`]
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+#[error("Invalid endianness: \"{0}\"")]
+pub struct ParseEndianError(String);
+
 /// Size = 0x14
-#[derive(Debug, Eq, PartialEq, Copy, Clone, BinRead, BinWrite)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, BinRead)]
+#[cfg_attr(feature = "std", derive(BinWrite))]
 struct ResHeader {
     magic: [char; 4],
     header_size: u16,
@@ -78,7 +272,8 @@ struct ResHeader {
 }
 
 /// Size = 0x0C
-#[derive(Debug, Copy, Clone, Eq, PartialEq, BinRead, BinWrite)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, BinRead)]
+#[cfg_attr(feature = "std", derive(BinWrite))]
 struct ResFatHeader {
     magic: [char; 4],
     header_size: u16,
@@ -87,7 +282,8 @@ struct ResFatHeader {
 }
 
 /// Size = 0x10
-#[derive(Debug, PartialEq, Eq, Copy, Clone, BinRead, BinWrite)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, BinRead)]
+#[cfg_attr(feature = "std", derive(BinWrite))]
 struct ResFatEntry {
     name_hash: u32,
     rel_name_opt_offset: u32,
@@ -96,7 +292,8 @@ struct ResFatEntry {
 }
 
 /// Size = 0x8
-#[derive(Debug, PartialEq, Eq, Copy, Clone, BinRead, BinWrite)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, BinRead)]
+#[cfg_attr(feature = "std", derive(BinWrite))]
 struct ResFntHeader {
     magic: [char; 4],
     header_size: u16,
@@ -107,3 +304,19 @@ struct ResFntHeader {
 const fn is_valid_alignment(alignment: usize) -> bool {
     alignment != 0 && (alignment & (alignment - 1)) == 0
 }
+
+/// Extends the lifetime of a buffer's contents to `'static`, so that an
+/// archive parsed from them can be stored next to the buffer that owns them.
+///
+/// # Safety
+///
+/// The contents must stay at the same address, unmodified, for as long as the
+/// returned slice is used. Buffers like `bytes::Bytes` and `memmap2::Mmap`
+/// keep their contents in place when the handle is moved, so this holds if
+/// the struct storing the archive declares it before the buffer (fields are
+/// dropped in declaration order) and only hands out the archive with a
+/// lifetime tied to the struct.
+#[cfg(any(feature = "bytes", feature = "mmap"))]
+unsafe fn extend_lifetime(data: &[u8]) -> &'static [u8] {
+    core::slice::from_raw_parts(data.as_ptr(), data.len())
+}
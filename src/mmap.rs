@@ -0,0 +1,41 @@
+//! Memory mapped archives, for reading large archives without loading them
+//! into memory first.
+use crate::parse::Result;
+use crate::*;
+use memmap2::Mmap;
+use std::{fs, path::Path};
+
+/// A SARC archive backed by a memory mapped file, as returned by
+/// [`Sarc::from_mmap`]. The archive is accessed with [`SarcMmap::sarc`].
+#[derive(Debug)]
+pub struct SarcMmap {
+    // Declared first so that it is dropped before the mapping it borrows.
+    sarc: Sarc<'static>,
+    _mmap: Mmap,
+}
+
+impl SarcMmap {
+    /// Get the archive, borrowing from the mapping
+    pub fn sarc(&self) -> &Sarc<'_> {
+        &self.sarc
+    }
+}
+
+impl Sarc<'_> {
+    /// Memory maps a file and parses it as a SARC archive without copying its
+    /// data. Yaz0 compressed files still have to be decompressed into memory.
+    ///
+    /// The file must not be modified while it is mapped, or the archive's
+    /// contents may change underneath it.
+    pub fn from_mmap<P: AsRef<Path>>(path: P) -> Result<SarcMmap> {
+        let file = fs::File::open(path)?;
+        // Modifying the file while it is mapped is documented to be unsupported.
+        let mmap = unsafe { Mmap::map(&file)? };
+        // `SarcMmap` declares the archive before the mapping it borrows.
+        let data = unsafe { extend_lifetime(&mmap) };
+        Ok(SarcMmap {
+            sarc: Sarc::new(data)?,
+            _mmap: mmap,
+        })
+    }
+}
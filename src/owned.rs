@@ -0,0 +1,39 @@
+//! Archives that share ownership of their data through [`bytes::Bytes`].
+use crate::parse::Result;
+use crate::*;
+use bytes::Bytes;
+
+/// A SARC archive that keeps its [`Bytes`] buffer alive, as returned by
+/// [`Sarc::from_bytes`]. Cloning it only clones the reference to the buffer.
+/// The archive is accessed with [`OwnedSarc::sarc`].
+#[derive(Debug, Clone)]
+pub struct OwnedSarc {
+    // Declared first so that it is dropped before the buffer it borrows.
+    sarc: Sarc<'static>,
+    bytes: Bytes,
+}
+
+impl OwnedSarc {
+    /// Get the archive, borrowing from the buffer
+    pub fn sarc(&self) -> &Sarc<'_> {
+        &self.sarc
+    }
+
+    /// Get the buffer the archive was parsed from
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl Sarc<'_> {
+    /// Parses a SARC archive from a shared buffer without copying it. Yaz0
+    /// compressed data still has to be decompressed into memory.
+    pub fn from_bytes(bytes: Bytes) -> Result<OwnedSarc> {
+        // `OwnedSarc` declares the archive before the buffer it borrows.
+        let data = unsafe { extend_lifetime(&bytes) };
+        Ok(OwnedSarc {
+            sarc: Sarc::new(data)?,
+            bytes,
+        })
+    }
+}
@@ -1,12 +1,24 @@
 use crate::*;
-use binread::{BinRead, BinReaderExt};
-use core::mem::size_of;
-use derivative::*;
-use std::{
-    borrow::Cow,
+use alloc::{
+    borrow::{Cow, ToOwned},
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use binread::{io::Cursor, BinRead, BinReaderExt};
+use core::{
     hash::{Hash, Hasher},
-    io::Cursor,
+    mem::size_of,
+    ops::{ControlFlow, Index},
 };
+use derivative::*;
+#[cfg(feature = "std")]
+use std::path::{Component, Path, PathBuf};
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+// Without std, the entry cache is not thread safe, so neither is `Sarc`.
+#[cfg(not(feature = "std"))]
+use core::cell::OnceCell as OnceLock;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -18,10 +30,24 @@ pub enum SarcError {
     InvalidData(String, String),
     #[error("A string in the name table was not terminated")]
     UnterminatedStringError,
+    #[error("Invalid Yaz0 compressed data")]
+    Yaz0Error,
     #[error("Invalid UTF file name")]
-    InvalidFileName(#[from] std::str::Utf8Error),
+    InvalidFileName(#[from] core::str::Utf8Error),
+    // binread only implements `Error` for its errors with std
+    #[cfg_attr(feature = "std", error(transparent))]
+    #[cfg_attr(not(feature = "std"), error("{0}"))]
+    ParseError(#[cfg_attr(feature = "std", from)] binread::Error),
+    #[cfg(feature = "std")]
     #[error(transparent)]
-    ParseError(#[from] binread::Error),
+    IOError(#[from] std::io::Error),
+}
+
+#[cfg(not(feature = "std"))]
+impl From<binread::Error> for SarcError {
+    fn from(error: binread::Error) -> Self {
+        SarcError::ParseError(error)
+    }
 }
 
 pub type Result<T> = core::result::Result<T, SarcError>;
@@ -32,6 +58,71 @@ fn find_null(data: &[u8]) -> Result<usize> {
         .ok_or(SarcError::UnterminatedStringError)
 }
 
+/// Joins an archive file name onto an output directory, rejecting names that
+/// would escape it
+#[cfg(feature = "std")]
+fn output_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    if relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(dir.join(relative))
+    } else {
+        Err(SarcError::InvalidData(
+            "file name".to_owned(),
+            name.to_owned(),
+        ))
+    }
+}
+
+fn decompress_if_needed(data: Cow<'_, [u8]>) -> Result<Cow<'_, [u8]>> {
+    if yaz0::is_yaz0(&data) {
+        yaz0::decompress(&data)
+            .map(Cow::Owned)
+            .ok_or(SarcError::Yaz0Error)
+    } else {
+        Ok(data)
+    }
+}
+
+/// How many candidates [`lift_multiplier`] checks before giving up
+const LIFT_MULTIPLIER_BUDGET: usize = 1 << 16;
+
+/// Find a multiplier matching all the given hashes one bit at a time. The low
+/// `n` bits of a name hash only depend on the low `n` bits of the multiplier,
+/// so each candidate can be checked before the higher bits are chosen.
+///
+/// Hashes that do not depend on some bits of the multiplier (e.g. those of
+/// one-character names) let both candidates pass, so the search can branch at
+/// every bit. It gives up once `budget` candidates have been checked.
+fn lift_multiplier(
+    entries: &[(&str, u32)],
+    multiplier: u32,
+    bit: u32,
+    budget: &mut usize,
+) -> Option<u32> {
+    if bit == 32 {
+        return Some(multiplier);
+    }
+    let mask = u32::MAX >> (31 - bit);
+    for candidate in [multiplier, multiplier | 1 << bit] {
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+        if entries
+            .iter()
+            .all(|(name, hash)| (hash_name(candidate, name) ^ hash) & mask == 0)
+        {
+            if let Some(multiplier) = lift_multiplier(entries, candidate, bit + 1, budget) {
+                return Some(multiplier);
+            }
+        }
+    }
+    None
+}
+
 #[inline(always)]
 fn read<T: BinRead>(endian: Endian, reader: &mut Cursor<&[u8]>) -> Result<T> {
     Ok(match endian {
@@ -39,16 +130,40 @@ fn read<T: BinRead>(endian: Endian, reader: &mut Cursor<&[u8]>) -> Result<T> {
         Endian::Little => reader.read_le()?,
     })
 }
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+/// How the name offsets stored in SFAT entries are decoded
+pub enum NameOffsetMode {
+    /// The offset is divided by 4 and flagged by `1 << 24`, with 0 marking a
+    /// nameless entry (standard layout, as used in BOTW). Other flags are
+    /// ignored when reading, but rejected by [`Sarc::new_validated`].
+    #[default]
+    Scaled,
+    /// The offset is a plain byte offset into the name table, with or without
+    /// a flag in the top byte, which is ignored. As with the scaled form, 0
+    /// marks a nameless entry.
+    Absolute,
+}
+
 #[derive(Derivative)]
 #[derivative(Debug, Clone)]
 /// A simple SARC archive reader
+///
+/// Cloning an archive that borrows its data only copies the reference to it,
+/// while an archive that owns its data (e.g. after Yaz0 decompression or
+/// [`Sarc::into_owned`]) copies the data as well.
 pub struct Sarc<'a> {
     num_files: u16,
     entries_offset: u16,
     hash_multiplier: u32,
     data_offset: u32,
     names_offset: u32,
+    file_size: u32,
+    version: u16,
     endian: Endian,
+    name_offset_mode: NameOffsetMode,
+    #[derivative(Debug = "ignore")]
+    entries: OnceLock<Vec<ResFatEntry>>,
     #[derivative(Debug = "ignore")]
     data: Cow<'a, [u8]>,
 }
@@ -68,15 +183,99 @@ impl Hash for Sarc<'_> {
     }
 }
 
+/// Indexing by name yields the file data, since `Index` has to return a
+/// reference. Panics if the file does not exist; use [`Sarc::get_file`] for a
+/// fallible lookup.
+impl Index<&str> for Sarc<'_> {
+    type Output = [u8];
+
+    fn index(&self, name: &str) -> &[u8] {
+        self.get_file(name)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| panic!("File {} not found in SARC", name))
+            .data
+    }
+}
+
+/// Indexing by position yields the file data, since `Index` has to return a
+/// reference. Panics if the index is out of range; use [`Sarc::file_at`] for a
+/// fallible lookup.
+impl Index<usize> for Sarc<'_> {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &[u8] {
+        self.file_at(index)
+            .unwrap_or_else(|err| panic!("{}", err))
+            .data
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Sarc<'a> {
+    type Error = SarcError;
+
+    /// Parses a SARC archive borrowing the data. See [`Sarc::new`].
+    fn try_from(data: &'a [u8]) -> Result<Self> {
+        Sarc::new(data)
+    }
+}
+
+impl TryFrom<Vec<u8>> for Sarc<'static> {
+    type Error = SarcError;
+
+    /// Parses a SARC archive taking ownership of the data. See [`Sarc::new`].
+    fn try_from(data: Vec<u8>) -> Result<Self> {
+        Sarc::new(data)
+    }
+}
+
 impl<'a> Sarc<'_> {
-    /// Parses a SARC archive from binary data
+    /// Parses a SARC archive from binary data. Yaz0 compressed data is
+    /// decompressed first, in which case the archive owns the decompressed
+    /// buffer.
     pub fn new<T: Into<Cow<'a, [u8]>>>(data: T) -> Result<Sarc<'a>> {
+        let data = decompress_if_needed(data.into())?;
+
+        let mut reader = Cursor::new(data.as_ref());
+        reader.set_position(6);
+        let endian: Endian = Endian::read(&mut reader)?;
+        Self::parse(data, endian, true)
+    }
+
+    /// Parses a SARC archive that may be zstd compressed (e.g. `.zs` files), in
+    /// which case the archive owns the decompressed buffer. Other data is
+    /// parsed like with [`Sarc::new`].
+    #[cfg(feature = "zstd")]
+    pub fn new_zstd<T: Into<Cow<'a, [u8]>>>(data: T) -> Result<Sarc<'a>> {
+        const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
         let data = data.into();
+        if data.starts_with(&ZSTD_MAGIC) {
+            Self::new(zstd::decode_all(data.as_ref())?)
+        } else {
+            Self::new(data)
+        }
+    }
+
+    /// Parses a SARC archive like [`Sarc::new`], but accepts any format
+    /// version instead of only `0x0100`, e.g. for prototype archives. The
+    /// version can be checked with [`Sarc::version`].
+    pub fn new_any_version<T: Into<Cow<'a, [u8]>>>(data: T) -> Result<Sarc<'a>> {
+        let data = decompress_if_needed(data.into())?;
 
         let mut reader = Cursor::new(data.as_ref());
         reader.set_position(6);
         let endian: Endian = Endian::read(&mut reader)?;
-        reader.set_position(0);
+        Self::parse(data, endian, false)
+    }
+
+    /// Parses a SARC archive from binary data using the specified endianness,
+    /// regardless of the byte order mark stored in the header
+    pub fn new_with_endian<T: Into<Cow<'a, [u8]>>>(data: T, endian: Endian) -> Result<Sarc<'a>> {
+        Self::parse(decompress_if_needed(data.into())?, endian, true)
+    }
+
+    fn parse(data: Cow<'a, [u8]>, endian: Endian, check_version: bool) -> Result<Sarc<'a>> {
+        let mut reader = Cursor::new(data.as_ref());
 
         let header: ResHeader = read(endian, &mut reader)?;
         if header.magic != SARC_MAGIC {
@@ -85,7 +284,7 @@ impl<'a> Sarc<'_> {
                 header.magic.iter().collect(),
             ));
         }
-        if header.version != 0x0100 {
+        if check_version && header.version != SARC_VERSION {
             return Err(SarcError::InvalidData(
                 "SARC version".to_owned(),
                 header.version.to_string(),
@@ -111,7 +310,7 @@ impl<'a> Sarc<'_> {
                 fat_header.header_size.to_string(),
             ));
         }
-        if (fat_header.num_files >> 0xE) != 0 {
+        if fat_header.num_files as usize > MAX_FILE_COUNT {
             return Err(SarcError::InvalidData(
                 "SFAT file count".to_owned(),
                 fat_header.num_files.to_string(),
@@ -154,94 +353,870 @@ impl<'a> Sarc<'_> {
             num_files,
             hash_multiplier,
             names_offset,
+            file_size: header.file_size,
+            version: header.version,
+            name_offset_mode: NameOffsetMode::default(),
+            entries: OnceLock::new(),
         })
     }
 
+    /// Reads and parses a SARC archive from a file
+    #[cfg(feature = "std")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Sarc<'static>> {
+        Sarc::new(std::fs::read(path)?)
+    }
+
+    /// Parses a SARC archive like [`Sarc::new`], then additionally checks that
+    /// the archive is not truncated, that every file's data range is well
+    /// formed and lies within the archive, that entries are sorted by name
+    /// hash (which [`Sarc::get_file`] relies on) and that named entries have
+    /// the standard `1 << 24` name offset flag
+    ///
+    /// Overlapping data ranges are accepted, since deduplicated archives
+    /// point several entries at the same data.
+    pub fn new_validated<T: Into<Cow<'a, [u8]>>>(data: T) -> Result<Sarc<'a>> {
+        let sarc = Self::new(data)?;
+        sarc.validate()?;
+        Ok(sarc)
+    }
+
+    /// Parses an uncompressed SARC archive at the start of `data`, which may
+    /// be followed by other data (e.g. in a concatenated stream). Returns the
+    /// archive, limited to its declared file size, and that size, which is the
+    /// number of bytes consumed.
+    pub fn new_from_prefix(data: &'a [u8]) -> Result<(Sarc<'a>, usize)> {
+        if yaz0::is_yaz0(data) {
+            return Err(SarcError::InvalidData(
+                "SARC magic".to_owned(),
+                "Yaz0".to_owned(),
+            ));
+        }
+        let sarc = Self::new(data)?;
+        let size = sarc.file_size as usize;
+        if size < sarc.data_offset as usize || size > data.len() {
+            return Err(SarcError::InvalidData(
+                "SARC file size".to_owned(),
+                size.to_string(),
+            ));
+        }
+        Ok((
+            Sarc {
+                data: Cow::Borrowed(&data[..size]),
+                ..sarc
+            },
+            size,
+        ))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.file_size as usize > self.data.len() {
+            return Err(SarcError::InvalidData(
+                "SARC file size".to_owned(),
+                self.file_size.to_string(),
+            ));
+        }
+        let mut prev_hash = 0;
+        for i in 0..self.num_files as usize {
+            let entry = self.entry_at(i)?;
+            if entry.data_begin > entry.data_end {
+                return Err(SarcError::InvalidData(
+                    format!("file {} data range", i),
+                    format!("{:#x}..{:#x}", entry.data_begin, entry.data_end),
+                ));
+            }
+            let data_end = self.absolute_data_offset(entry.data_end)?;
+            if data_end > self.file_size as usize {
+                return Err(SarcError::InvalidData(
+                    format!("file {} data end", i),
+                    format!("{:#x}", data_end),
+                ));
+            }
+            if entry.name_hash < prev_hash {
+                return Err(SarcError::InvalidData(
+                    format!("file {} name hash", i),
+                    format!("{:#x}", entry.name_hash),
+                ));
+            }
+            prev_hash = entry.name_hash;
+            let flag = entry.rel_name_opt_offset >> 24;
+            if entry.rel_name_opt_offset != 0 && flag != 1 {
+                return Err(SarcError::InvalidData(
+                    format!("file {} name offset flag", i),
+                    format!("{:#x}", flag),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Attempts to parse an archive whose byte order mark may be wrong. The
+    /// endianness indicated by the BOM is tried first, then the opposite one,
+    /// and the first attempt whose file offsets are consistent with the data
+    /// is returned.
+    ///
+    /// This is a heuristic intended for recovering mislabeled dumps. If
+    /// neither endianness yields consistent offsets, the error from the first
+    /// attempt is returned.
+    pub fn try_both_endians<T: Into<Cow<'a, [u8]>>>(data: T) -> Result<Sarc<'a>> {
+        let data = decompress_if_needed(data.into())?;
+        let mut reader = Cursor::new(data.as_ref());
+        reader.set_position(6);
+        let first = Endian::read(&mut reader).unwrap_or(Endian::Big);
+        let endian = [first, first.opposite()]
+            .into_iter()
+            .find(|endian| {
+                Sarc::parse(Cow::Borrowed(data.as_ref()), *endian, true)
+                    .map(|sarc| sarc.has_consistent_offsets())
+                    .unwrap_or(false)
+            })
+            .unwrap_or(first);
+        Self::parse(data, endian, true)
+    }
+
+    fn has_consistent_offsets(&self) -> bool {
+        let data_len = self.data.len();
+        self.data_offset as usize <= data_len
+            && (0..self.num_files as usize).all(|i| match self.entry_at(i) {
+                Ok(entry) => {
+                    entry.data_begin <= entry.data_end
+                        && self
+                            .absolute_data_offset(entry.data_end)
+                            .map_or(false, |end| end <= data_len)
+                }
+                Err(_) => false,
+            })
+    }
+
+    /// Converts the archive into one that owns its data, copying the data if
+    /// it is currently borrowed
+    pub fn into_owned(self) -> Sarc<'static> {
+        Sarc {
+            num_files: self.num_files,
+            entries_offset: self.entries_offset,
+            hash_multiplier: self.hash_multiplier,
+            data_offset: self.data_offset,
+            names_offset: self.names_offset,
+            file_size: self.file_size,
+            version: self.version,
+            endian: self.endian,
+            name_offset_mode: self.name_offset_mode,
+            entries: self.entries,
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+
     /// Get the number of files that are stored in the archive
     pub fn file_count(&self) -> usize {
         self.num_files as usize
     }
 
+    /// Get the number of entries that refer to the name table, without reading
+    /// the names. Entries that cannot be read are not counted.
+    pub fn named_count(&self) -> usize {
+        (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .filter(|entry| self.name_offset(entry).is_some())
+            .count()
+    }
+
+    /// Get the number of nameless entries, which are only identified by their
+    /// name hash. Entries that cannot be read are not counted.
+    pub fn unnamed_count(&self) -> usize {
+        (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .filter(|entry| self.name_offset(entry).is_none())
+            .count()
+    }
+
     /// Get the offset to the beginning of file data
     pub fn data_offset(&self) -> usize {
         self.data_offset as usize
     }
 
+    /// Get the absolute position where the first file's data begins, which
+    /// may be after the data offset if there is padding before it. Returns the
+    /// data offset for archives without files.
+    pub fn first_data_position(&self) -> usize {
+        (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .map(|entry| entry.data_begin as usize)
+            .min()
+            .unwrap_or(0)
+            + self.data_offset as usize
+    }
+
+    /// Get the size of the archive once loaded into memory, as declared in the
+    /// header. This includes headers, tables and all alignment padding.
+    pub fn loaded_size(&self) -> usize {
+        self.file_size as usize
+    }
+
+    /// Get the file size declared in the header, which is the same as
+    /// [`Sarc::loaded_size`]. It can differ from the size of the data the
+    /// archive was parsed from, e.g. if the file was truncated or padded.
+    pub fn declared_file_size(&self) -> u32 {
+        self.file_size
+    }
+
+    /// Get the format version declared in the header
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Get the combined size of the data of all files in the archive. Data
+    /// shared by several files, as in deduplicated archives, is counted once
+    /// for each of them.
+    pub fn total_data_size(&self) -> usize {
+        (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .map(|entry| entry.data_end.saturating_sub(entry.data_begin) as usize)
+            .sum()
+    }
+
+    /// Get the number of bytes in the data section (everything from the data
+    /// offset to the end of the archive) that do not belong to any file
+    pub fn padding_size(&self) -> usize {
+        self.loaded_size()
+            .saturating_sub(self.data_offset as usize)
+            .saturating_sub(self.stored_data_size())
+    }
+
+    /// Get the number of bytes in the data section that belong to at least
+    /// one file, counting data shared by several files only once
+    fn stored_data_size(&self) -> usize {
+        let section_size = self.loaded_size().saturating_sub(self.data_offset as usize);
+        let mut ranges: Vec<(usize, usize)> = (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .map(|entry| {
+                (
+                    (entry.data_begin as usize).min(section_size),
+                    (entry.data_end as usize).min(section_size),
+                )
+            })
+            .collect();
+        ranges.sort_unstable();
+        let mut size = 0;
+        let mut covered = 0;
+        for (begin, end) in ranges {
+            let begin = begin.max(covered);
+            if end > begin {
+                size += end - begin;
+                covered = end;
+            }
+        }
+        size
+    }
+
+    /// Guess the byte used to fill the alignment gaps before, between and
+    /// after the file data, from the first such gap. Names are padded with
+    /// zeros regardless, so their padding is not considered. Returns `None`
+    /// if there are no gaps.
+    pub fn guess_padding_byte(&self) -> Option<u8> {
+        let mut ranges: Vec<(usize, usize)> = (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .filter_map(|entry| {
+                Some((
+                    self.absolute_data_offset(entry.data_begin).ok()?,
+                    self.absolute_data_offset(entry.data_end).ok()?,
+                ))
+            })
+            .collect();
+        ranges.sort_unstable();
+        let archive_end = (self.file_size as usize).min(self.data.len());
+        let mut prev_end = self
+            .overhead()
+            .header_size
+            .next_multiple_of(NAME_OFFSET_SCALE);
+        for (begin, end) in ranges.into_iter().chain([(archive_end, archive_end)]) {
+            // The last byte of a gap cannot be part of a name's padding
+            if begin > prev_end {
+                return self.data.get(begin - 1).copied();
+            }
+            prev_end = prev_end.max(end);
+        }
+        None
+    }
+
+    /// Get a breakdown of the archive's size into headers and tables, file
+    /// data and padding. The padding includes [`Sarc::padding_size`] as well
+    /// as the gap between the end of the name table and the data offset.
+    pub fn overhead(&self) -> SarcStats {
+        let names_end = (0..self.num_files as usize)
+            .filter_map(|i| self.entry_at(i).ok())
+            .filter_map(|entry| {
+                let name = self.entry_name_bytes(&entry).ok()??;
+                Some(self.name_offset(&entry)? + name.len() + 1)
+            })
+            .fold(self.names_offset as usize, usize::max)
+            .min(self.data_offset as usize);
+        SarcStats {
+            header_size: names_end,
+            data_size: self.stored_data_size(),
+            padding_size: self.padding_size() + (self.data_offset as usize - names_end),
+        }
+    }
+
+    /// Set how name offsets in the file table are decoded. Only needed for
+    /// archives made by non-standard tools; the default is
+    /// [`NameOffsetMode::Scaled`].
+    pub fn set_name_offset_mode(&mut self, mode: NameOffsetMode) {
+        self.name_offset_mode = mode
+    }
+
+    /// Get the raw SFAT entry array: `0x10` bytes per file, starting right
+    /// after the SFAT header and ending right before the SFNT header
+    pub fn sfat_bytes(&self) -> &[u8] {
+        let begin = self.entries_offset as usize;
+        &self.data[begin..begin + size_of::<ResFatEntry>() * self.num_files as usize]
+    }
+
+    /// Get the raw name table, starting at the SFNT header and ending at the
+    /// data offset (so any padding before the file data is included)
+    pub fn sfnt_bytes(&self) -> &[u8] {
+        let end = (self.data_offset as usize).min(self.data.len());
+        &self.data[self.names_offset as usize - 0x8..end]
+    }
+
+    /// Get the multiplier used to hash file names
+    pub fn hash_multiplier(&self) -> u32 {
+        self.hash_multiplier
+    }
+
+    /// Returns true if the SFAT header declares the standard name hash
+    /// multiplier (`0x65`). Archives with another multiplier are valid, but
+    /// their name hashes can only be reproduced with that multiplier.
+    pub fn uses_standard_multiplier(&self) -> bool {
+        self.hash_multiplier == DEFAULT_HASH_MULTIPLIER
+    }
+
+    /// Derive the multiplier that reproduces the name hashes stored in the
+    /// archive, which can differ from the one declared in the SFAT header.
+    /// The default `0x65` is checked first. Returns `None` if no single
+    /// multiplier matches every named entry, if the entries cannot be read or
+    /// if the hashes constrain the multiplier too little to find one quickly.
+    pub fn guess_hash_multiplier(&self) -> Option<u32> {
+        let mut entries = Vec::with_capacity(self.num_files as usize);
+        for i in 0..self.num_files as usize {
+            let entry = self.entry_at(i).ok()?;
+            if let Some(name) = self.entry_name(&entry).ok()? {
+                entries.push((name, entry.name_hash));
+            }
+        }
+
+        if entries
+            .iter()
+            .all(|(name, hash)| hash_name(DEFAULT_HASH_MULTIPLIER, name) == *hash)
+        {
+            return Some(DEFAULT_HASH_MULTIPLIER);
+        }
+        let mut budget = LIFT_MULTIPLIER_BUDGET;
+        lift_multiplier(&entries, 0, 0, &mut budget)
+    }
+
     /// Get the archive endianness
     pub fn endian(&self) -> Endian {
         self.endian
     }
 
-    /// Get a file by name
+    /// Get a file by name. If several entries share the file's name hash, the
+    /// stored names are compared to find the right one.
+    ///
+    /// Names are hashed with the multiplier declared in the SFAT header. If an
+    /// archive was written with a different one, lookups fail even for files
+    /// that exist; see [`Sarc::uses_standard_multiplier`] and
+    /// [`Sarc::guess_hash_multiplier`] to diagnose this.
     pub fn get_file(&self, file: &str) -> Result<Option<File>> {
-        if self.num_files == 0 {
-            return Ok(None);
-        }
-        let needle_hash = hash_name(self.hash_multiplier, file);
-        let mut a: u32 = 0;
-        let mut b: u32 = self.num_files as u32 - 1;
-        let mut reader = Cursor::new(self.data.as_ref());
-        while a <= b {
-            let m: u32 = (a + b) as u32 / 2;
-            reader.set_position(self.entries_offset as u64 + 0x10 * m as u64);
-            let hash: u32 = read(self.endian, &mut reader)?;
-            match needle_hash.cmp(&hash) {
-                std::cmp::Ordering::Less => b = m - 1,
-                std::cmp::Ordering::Greater => a = m + 1,
-                std::cmp::Ordering::Equal => return Ok(Some(self.file_at(m as usize)?)),
+        match self.find_entry_index(file)? {
+            Some(index) => self.file_at(index).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Find the SFAT index of a file by name, using a binary search over the
+    /// name hashes. If several entries share the file's name hash, the stored
+    /// names are compared to find the right one.
+    pub fn find_entry_index(&self, name: &str) -> Result<Option<usize>> {
+        Ok(self.find_entry_from(0, name)?.1)
+    }
+
+    /// Get several files by name, returning the result of each lookup in the
+    /// order of `names`. The names are looked up in name hash order, so every
+    /// search only covers the entries after the previous one.
+    pub fn get_files<'s>(&'s self, names: &[&str]) -> Vec<Result<Option<File<'s>>>> {
+        let mut order: Vec<usize> = (0..names.len()).collect();
+        order.sort_by_cached_key(|&i| hash_name(self.hash_multiplier, names[i]));
+        let mut results: Vec<Result<Option<File>>> = names.iter().map(|_| Ok(None)).collect();
+        let mut start = 0;
+        for i in order {
+            results[i] = self
+                .find_entry_from(start, names[i])
+                .and_then(|(lower, index)| {
+                    start = lower;
+                    index.map(|index| self.file_at(index)).transpose()
+                });
+        }
+        results
+    }
+
+    /// Find the SFAT index of a file by name, only searching the entries from
+    /// `start` on. Also returns the index of the first entry with the name's
+    /// hash (or a greater one), where a search for a greater hash can start.
+    fn find_entry_from(&self, start: usize, name: &str) -> Result<(usize, Option<usize>)> {
+        let needle_hash = hash_name(self.hash_multiplier, name);
+        let lower = self.lower_bound(start, needle_hash)?;
+        let mut index = lower;
+        while index < self.num_files as usize {
+            let entry = self.entry_at(index)?;
+            if entry.name_hash != needle_hash {
+                break;
+            }
+            if self.entry_name(&entry)? == Some(name) {
+                return Ok((lower, Some(index)));
+            }
+            index += 1;
+        }
+        Ok((lower, None))
+    }
+
+    /// Get a file by name by comparing the stored names of all entries in
+    /// turn. This is slower than [`Sarc::get_file`], but still finds the file
+    /// in archives whose name hashes are wrong or not sorted. Entries that
+    /// cannot be read are skipped.
+    pub fn get_file_linear(&self, name: &str) -> Result<Option<File>> {
+        for i in 0..self.num_files as usize {
+            let entry = match self.entry_at(i) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            if let Ok(Some(entry_name)) = self.entry_name(&entry) {
+                if entry_name == name {
+                    return self.file_at(i).map(Some);
+                }
             }
         }
         Ok(None)
     }
 
+    /// Get a file by its name hash, e.g. for nameless entries. If several
+    /// entries share the hash, the first one is returned.
+    pub fn get_file_by_hash(&self, hash: u32) -> Result<Option<File>> {
+        let index = self.lower_bound(0, hash)?;
+        if index < self.num_files as usize && self.entry_at(index)?.name_hash == hash {
+            self.file_at(index).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get the data of a file by name. See [`Sarc::get_file`].
+    pub fn get_file_data(&self, name: &str) -> Result<Option<&[u8]>> {
+        Ok(self.get_file(name)?.map(|file| file.data))
+    }
+
+    /// Find the index of the first entry from `start` on whose name hash is
+    /// not less than `hash`
+    fn lower_bound(&self, start: usize, hash: u32) -> Result<usize> {
+        let mut a = start;
+        let mut b = self.num_files as usize;
+        while a < b {
+            let m = (a + b) / 2;
+            if self.entry_at(m)?.name_hash < hash {
+                a = m + 1;
+            } else {
+                b = m;
+            }
+        }
+        Ok(a)
+    }
+
+    /// Returns true if the archive contains a file with the given name
+    pub fn contains(&self, name: &str) -> bool {
+        matches!(self.get_file(name), Ok(Some(_)))
+    }
+
     /// Get a file by index. Returns error if index > file count.
     pub fn file_at(&self, index: usize) -> Result<File> {
         if index >= self.num_files as usize {
             return Err(SarcError::OutOfRange(index));
         }
 
+        let entry = self.entry_at(index)?;
+        Ok(File {
+            name: self.entry_name(&entry)?,
+            name_hash: entry.name_hash,
+            data: self.entry_data(&entry)?,
+        })
+    }
+
+    /// Get a file by index like [`Sarc::file_at`], but replace invalid UTF-8
+    /// in its name instead of failing. Other errors are still returned.
+    pub fn file_at_lossy(&self, index: usize) -> Result<LossyFile> {
+        if index >= self.num_files as usize {
+            return Err(SarcError::OutOfRange(index));
+        }
+
+        let entry = self.entry_at(index)?;
+        Ok(LossyFile {
+            name: self.entry_name_bytes(&entry)?.map(String::from_utf8_lossy),
+            name_hash: entry.name_hash,
+            data: self.entry_data(&entry)?,
+        })
+    }
+
+    /// Converts an offset relative to the data section into one relative to
+    /// the start of the archive
+    fn absolute_data_offset(&self, offset: u32) -> Result<usize> {
+        (self.data_offset as usize)
+            .checked_add(offset as usize)
+            .ok_or_else(|| {
+                SarcError::InvalidData(
+                    "file data offset".to_owned(),
+                    format!("{:#x} + {:#x}", self.data_offset, offset),
+                )
+            })
+    }
+
+    fn entry_data(&self, entry: &ResFatEntry) -> Result<&[u8]> {
+        let data_begin = self.absolute_data_offset(entry.data_begin)?;
+        let data_end = self.absolute_data_offset(entry.data_end)?;
+        // Data past the declared file size does not belong to the archive
+        let archive_end = (self.file_size as usize).min(self.data.len());
+        self.data[..archive_end]
+            .get(data_begin..data_end)
+            .ok_or_else(|| {
+                SarcError::InvalidData(
+                    "file data range".to_owned(),
+                    format!("{:#x}..{:#x}", data_begin, data_end),
+                )
+            })
+    }
+
+    fn entry_name(&self, entry: &ResFatEntry) -> Result<Option<&str>> {
+        match self.entry_name_bytes(entry)? {
+            Some(name) => Ok(Some(core::str::from_utf8(name)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn entry_name_bytes(&self, entry: &ResFatEntry) -> Result<Option<&[u8]>> {
+        let name_offset = match self.name_offset(entry) {
+            Some(name_offset) => name_offset,
+            None => return Ok(None),
+        };
+        let name_data = self.data.get(name_offset..).ok_or_else(|| {
+            SarcError::InvalidData("name offset".to_owned(), name_offset.to_string())
+        })?;
+        let term_pos = find_null(name_data)?;
+        Ok(Some(&name_data[..term_pos]))
+    }
+
+    fn name_offset(&self, entry: &ResFatEntry) -> Option<usize> {
+        if entry.rel_name_opt_offset == 0 {
+            return None;
+        }
+        let rel_offset = (entry.rel_name_opt_offset & 0xFFFFFF) as usize;
+        Some(match self.name_offset_mode {
+            NameOffsetMode::Scaled => self.names_offset as usize + rel_offset * NAME_OFFSET_SCALE,
+            NameOffsetMode::Absolute => self.names_offset as usize + rel_offset,
+        })
+    }
+
+    /// Get the SFAT entry at the given index. All entries are parsed the first
+    /// time this is called; entries that could not be parsed are read again
+    /// so that the error is reported.
+    fn entry_at(&self, index: usize) -> Result<ResFatEntry> {
+        let entries = self.entries.get_or_init(|| {
+            (0..self.num_files as usize)
+                .map_while(|i| self.read_entry(i).ok())
+                .collect()
+        });
+        match entries.get(index) {
+            Some(entry) => Ok(*entry),
+            None => self.read_entry(index),
+        }
+    }
+
+    fn read_entry(&self, index: usize) -> Result<ResFatEntry> {
         let entry_offset = self.entries_offset as usize + size_of::<ResFatEntry>() * index;
-        let entry: ResFatEntry = read(self.endian, &mut Cursor::new(&self.data[entry_offset..]))?;
+        let entry_data = self.data.get(entry_offset..).ok_or_else(|| {
+            SarcError::InvalidData("SFAT entry offset".to_owned(), entry_offset.to_string())
+        })?;
+        read(self.endian, &mut Cursor::new(entry_data))
+    }
 
-        Ok(File {
-            name: if entry.rel_name_opt_offset != 0 {
-                let name_offset = self.names_offset as usize
-                    + (entry.rel_name_opt_offset & 0xFFFFFF) as usize * 4;
-                let term_pos = find_null(&self.data[name_offset..])?;
-                Some(std::str::from_utf8(
-                    &self.data[name_offset..name_offset + term_pos],
-                )?)
-            } else {
-                None
-            },
-            data: &self.data[(self.data_offset + entry.data_begin) as usize
-                ..(self.data_offset + entry.data_end) as usize],
+    /// Returns an iterator over the contained files, in SFAT order (sorted by
+    /// name hash). Entries that cannot be read are skipped, so the iteration
+    /// position only matches the entry index for well-formed archives; use
+    /// [`Sarc::indexed_files`] when the index matters.
+    pub fn files(&self) -> FileIter<'_> {
+        FileIter {
+            sarc: self,
+            front: 0,
+            back: self.num_files as usize,
+        }
+    }
+
+    /// Returns an iterator over the contained files in SFAT order, yielding an
+    /// error for each entry that cannot be read instead of skipping it
+    pub fn try_files(&self) -> impl Iterator<Item = Result<File>> {
+        (0..self.num_files as usize).map(move |i| self.file_at(i))
+    }
+
+    /// Returns the contained files sorted by name, with nameless entries last.
+    /// Entries that cannot be read are skipped.
+    pub fn files_sorted(&self) -> Vec<File> {
+        let mut files: Vec<File> = self.files().collect();
+        // `None` sorts before `Some`, so compare whether the name is missing first
+        files.sort_by(|a, b| {
+            a.name
+                .is_none()
+                .cmp(&b.name.is_none())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        files
+    }
+
+    /// Returns an iterator over copies of the names and data of the contained
+    /// files in SFAT order, which do not borrow the archive. Entries that
+    /// cannot be read are skipped.
+    pub fn owned_entries(&self) -> impl Iterator<Item = (Option<String>, Vec<u8>)> + '_ {
+        self.files()
+            .map(|file| (file.name.map(str::to_owned), file.data.to_vec()))
+    }
+
+    /// Returns an iterator over the files whose name has the given extension
+    /// (without the dot, e.g. “sbfres”), as returned by [`File::extension`].
+    /// Nameless entries are skipped.
+    pub fn files_by_extension<'s>(&'s self, ext: &'s str) -> impl Iterator<Item = File<'s>> {
+        self.files()
+            .filter(move |file| file.extension() == Some(ext))
+    }
+
+    /// Returns an iterator over the contained files along with their SFAT entry
+    /// index, which can be passed to [`Sarc::file_at`] or [`Sarc::alignment_of`].
+    /// Entries that cannot be read are skipped.
+    pub fn indexed_files(&self) -> impl Iterator<Item = (usize, File)> {
+        (0..self.num_files as usize).filter_map(move |i| self.file_at(i).ok().map(|file| (i, file)))
+    }
+
+    /// Calls `f` with the index, name and data of each entry in SFAT order,
+    /// stopping early if it returns [`ControlFlow::Break`]. Unlike the file
+    /// iterators, this fails on the first entry that cannot be read.
+    pub fn for_each_entry<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, Option<&str>, &[u8]) -> ControlFlow<()>,
+    {
+        for i in 0..self.num_files as usize {
+            let entry = self.entry_at(i)?;
+            if f(i, self.entry_name(&entry)?, self.entry_data(&entry)?).is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts all files to a directory, creating parent directories as
+    /// needed. Nameless entries are skipped. Names that would escape the
+    /// directory (absolute paths or `..` components) are rejected with an error.
+    #[cfg(feature = "std")]
+    pub fn extract_to<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        for file in self.files() {
+            if let Some(name) = file.name {
+                let path = output_path(dir, name)?;
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(path, file.data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts all files to a directory like [`Sarc::extract_to`], but writes
+    /// them in parallel. All names are checked before anything is written.
+    #[cfg(feature = "rayon")]
+    pub fn par_extract_to<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        use rayon::prelude::*;
+        let dir = dir.as_ref();
+        let files = self
+            .files()
+            .filter_map(|file| file.name.map(|name| (name, file.data)))
+            .map(|(name, data)| Ok((output_path(dir, name)?, data)))
+            .collect::<Result<Vec<_>>>()?;
+        files.into_par_iter().try_for_each(|(path, data)| {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, data)?;
+            Ok(())
         })
     }
 
-    /// Returns an iterator over the contained files
-    pub fn files(&'_ self) -> impl Iterator<Item = File<'_>> {
-        let count = self.num_files;
-        (0..count).flat_map(move |i| self.file_at(i as usize).ok())
+    /// Returns an iterator over the names of all named files, without
+    /// touching their data
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        (0..self.num_files as usize).filter_map(move |i| {
+            self.entry_at(i)
+                .ok()
+                .and_then(|entry| self.entry_name(&entry).ok().flatten())
+        })
+    }
+
+    /// Copies the names and data of all named files into an owned map, in
+    /// SFAT order. Nameless entries are skipped. After editing, the map can be
+    /// used to extend a writer made with `SarcWriter::from_sarc`, which keeps
+    /// the archive's settings.
+    #[cfg(feature = "std")]
+    pub fn to_map(&self) -> indexmap::IndexMap<String, Vec<u8>> {
+        self.files()
+            .filter_map(|f| f.name.map(|name| (name.to_owned(), f.data.to_vec())))
+            .collect()
+    }
+
+    /// Consumes the archive and returns the names and data of all named files
+    /// as owned pairs, in archive order. Nameless entries are skipped.
+    pub fn into_entries(self) -> Vec<(String, Vec<u8>)> {
+        self.files()
+            .filter_map(|f| f.name.map(|name| (name.to_owned(), f.data.to_vec())))
+            .collect()
     }
 
-    /// Guess the minimum data alignment for files that are stored in the archive
-    pub fn guess_min_alignment(&self) -> usize {
-        const MIN_ALIGNMENT: u32 = 4;
+    /// Guess the minimum data alignment for files that are stored in the archive.
+    /// Returns 4 for an empty archive.
+    pub fn guess_min_alignment(&self) -> Result<usize> {
+        const MIN_ALIGNMENT: usize = 4;
         let mut gcd = MIN_ALIGNMENT;
-        let mut reader = Cursor::new(&self.data[self.entries_offset as usize..]);
-        for _ in 0..self.num_files {
-            let entry: ResFatEntry = read(self.endian, &mut reader).unwrap();
-            gcd = num::integer::gcd(gcd, self.data_offset + entry.data_begin);
+        for i in 0..self.num_files as usize {
+            let entry = self.entry_at(i)?;
+            gcd = num::integer::gcd(gcd, self.absolute_data_offset(entry.data_begin)?);
         }
 
-        if !is_valid_alignment(gcd as usize) {
-            return MIN_ALIGNMENT as usize;
+        if !is_valid_alignment(gcd) {
+            return Ok(MIN_ALIGNMENT);
         }
-        gcd as usize
+        Ok(gcd)
     }
 
-    /// Returns true is each archive contains the same files
+    /// Returns the alignment the file at the given index is stored at, that
+    /// is the largest power of two dividing its absolute data offset
+    pub fn alignment_of(&self, index: usize) -> Result<usize> {
+        if index >= self.num_files as usize {
+            return Err(SarcError::OutOfRange(index));
+        }
+
+        let entry = self.entry_at(index)?;
+        let offset = self.absolute_data_offset(entry.data_begin)?;
+        if offset == 0 {
+            return Err(SarcError::InvalidData(
+                "file data offset".to_owned(),
+                offset.to_string(),
+            ));
+        }
+        Ok(1 << offset.trailing_zeros())
+    }
+
+    /// Returns the name, location, alignment and name hash of each file in
+    /// SFAT order, e.g. to generate an index of the archive. Entries that
+    /// cannot be read are skipped.
+    pub fn manifest(&self) -> Vec<FileInfo> {
+        self.indexed_files()
+            .filter_map(|(i, file)| {
+                Some(FileInfo {
+                    name: file.name.map(str::to_owned),
+                    offset: self
+                        .absolute_data_offset(self.entry_at(i).ok()?.data_begin)
+                        .ok()?,
+                    size: file.data.len(),
+                    alignment: self.alignment_of(i).ok()?,
+                    hash: file.name_hash,
+                })
+            })
+            .collect()
+    }
+
+    /// Compares the files in this archive with those in another one by name.
+    /// Nameless entries are ignored.
+    pub fn diff(&self, other: &Sarc) -> SarcDiff {
+        let mut diff = SarcDiff::default();
+        for file in self.files() {
+            let name = match file.name {
+                Some(name) => name,
+                None => continue,
+            };
+            match other.get_file(name) {
+                Ok(Some(other_file)) => {
+                    if other_file.data != file.data {
+                        diff.changed.push(name.to_owned());
+                    }
+                }
+                _ => diff.only_in_self.push(name.to_owned()),
+            }
+        }
+        for name in other.files().filter_map(|file| file.name) {
+            if !matches!(self.get_file(name), Ok(Some(_))) {
+                diff.only_in_other.push(name.to_owned());
+            }
+        }
+        diff
+    }
+
+    /// Computes a fingerprint of the archive's named files that does not depend
+    /// on their order, alignment or the archive's endianness, e.g. for use as a
+    /// cache key. The hash (64-bit FNV-1a) is stable across versions and
+    /// platforms, but is not cryptographic. Nameless entries are ignored.
+    pub fn content_hash(&self) -> u64 {
+        let mut files: Vec<(&str, &[u8])> = self
+            .files()
+            .filter_map(|file| file.name.map(|name| (name, file.data)))
+            .collect();
+        files.sort_unstable();
+
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+        for (name, data) in files {
+            // Lengths are included so that the boundaries between names and
+            // data cannot shift without changing the hash.
+            write(&(name.len() as u64).to_le_bytes());
+            write(name.as_bytes());
+            write(&(data.len() as u64).to_le_bytes());
+            write(data);
+        }
+        hash
+    }
+
+    /// Guess whether the archive was written in legacy mode (see
+    /// `SarcWriter::set_legacy_mode`), which aligns nested SARC archives to
+    /// 0x2000. Returns false if the archive contains no nested archives, since
+    /// there is nothing to tell the modes apart by then.
+    pub fn is_legacy(&self) -> bool {
+        let mut nested = self
+            .indexed_files()
+            .filter(|(_, file)| is_file_sarc(file.data))
+            .peekable();
+        nested.peek().is_some()
+            && nested
+                .all(|(i, _)| matches!(self.alignment_of(i), Ok(alignment) if alignment >= 0x2000))
+    }
+
+    /// Returns true if both archives contain files with the same names and
+    /// data, regardless of their order, alignment and the archives' endianness
+    /// or hash multiplier. Nameless entries are ignored. Unlike `==`, which
+    /// compares the raw archive bytes, this compares the logical contents.
+    pub fn content_eq(&self, other: &Sarc) -> bool {
+        self.diff(other).is_empty()
+    }
+
+    /// Returns true is each archive contains the same files in the same
+    /// order. Files are compared by name and data, so archives written with
+    /// different hash multipliers can be equal; see [`File`]'s `PartialEq`.
     pub fn are_files_equal(sarc1: &Sarc, sarc2: &Sarc) -> bool {
         if sarc1.file_count() != sarc2.file_count() {
             return false;
@@ -256,9 +1231,109 @@ impl<'a> Sarc<'_> {
     }
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The size breakdown of an archive, as returned by [`Sarc::overhead`]. For
+/// well-formed archives, the sizes add up to [`Sarc::loaded_size`], since data
+/// shared by several files is only counted once.
+pub struct SarcStats {
+    /// Size of the headers, the file table and the name table
+    pub header_size: usize,
+    /// Size of the file data. Unlike [`Sarc::total_data_size`], data shared by
+    /// several files is only counted once.
+    pub data_size: usize,
+    /// Bytes used for alignment between the tables and the file data
+    pub padding_size: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// Information about a file in an archive, as returned by [`Sarc::manifest`]
+pub struct FileInfo {
+    /// File name, if the entry is named
+    pub name: Option<String>,
+    /// Offset of the file data from the start of the archive
+    pub offset: usize,
+    /// Size of the file data
+    pub size: usize,
+    /// Alignment the file data is stored at, see [`Sarc::alignment_of`]
+    pub alignment: usize,
+    /// Name hash stored in the file table
+    pub hash: u32,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The differences between the files of two archives, as returned by
+/// [`Sarc::diff`]
+pub struct SarcDiff {
+    /// Names of files only present in the first archive
+    pub only_in_self: Vec<String>,
+    /// Names of files only present in the second archive
+    pub only_in_other: Vec<String>,
+    /// Names of files present in both archives with different data
+    pub changed: Vec<String>,
+}
+
+impl SarcDiff {
+    /// Returns true if both archives contain the same files
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// An iterator over the files in a SARC archive.
+///
+/// Entries that cannot be parsed are skipped, so the number of files yielded
+/// is only bounded by the number of remaining entries.
+#[derive(Debug, Clone)]
+pub struct FileIter<'a> {
+    sarc: &'a Sarc<'a>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for FileIter<'a> {
+    type Item = File<'a>;
+
+    fn next(&mut self) -> Option<File<'a>> {
+        while self.front < self.back {
+            let index = self.front;
+            self.front += 1;
+            if let Ok(file) = self.sarc.file_at(index) {
+                return Some(file);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Any of the remaining entries may fail to parse and be skipped
+        (0, Some(self.back - self.front))
+    }
+}
+
+impl DoubleEndedIterator for FileIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            self.back -= 1;
+            if let Ok(file) = self.sarc.file_at(self.back) {
+                return Some(file);
+            }
+        }
+        None
+    }
+}
+
+impl<'a> IntoIterator for &'a Sarc<'_> {
+    type Item = File<'a>;
+    type IntoIter = FileIter<'a>;
+
+    fn into_iter(self) -> FileIter<'a> {
+        self.files()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{Endian, Sarc};
+    use crate::{CompressionLevel, Endian, NameOffsetMode, Sarc, SarcWriter};
     use std::fs::read;
     #[test]
     fn parse_sarc() {
@@ -266,7 +1341,7 @@ mod tests {
         let sarc = Sarc::new(&data).unwrap();
         assert_eq!(sarc.endian(), Endian::Big);
         assert_eq!(sarc.file_count(), 10);
-        assert_eq!(sarc.guess_min_alignment(), 4);
+        assert_eq!(sarc.guess_min_alignment().unwrap(), 4);
         for file in &[
             "NavMesh/CDungeon/Dungeon119/Dungeon119.shknm2",
             "Map/CDungeon/Dungeon119/Dungeon119_Static.smubin",
@@ -284,4 +1359,344 @@ mod tests {
                 .unwrap_or_else(|| panic!("Could not find file {}", file));
         }
     }
+
+    #[test]
+    fn guess_hash_multiplier() {
+        let mut writer = SarcWriter::new(Endian::Little);
+        writer.set_hash_multiplier(0x1F).unwrap();
+        writer.add_file("Actor/Pack/Test.sbactorpack", b"a".to_vec());
+        writer.add_file("Map/MainField/A-1/A-1_Static.smubin", b"b".to_vec());
+        let data = writer.write_to_bytes().unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        assert_eq!(sarc.guess_hash_multiplier(), Some(0x1F));
+    }
+
+    #[test]
+    fn guess_hash_multiplier_unconstrained() {
+        let mut data = SarcWriter::new(Endian::Little)
+            .with_file("a", b"data".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        // The hash of a one-character name does not depend on the multiplier,
+        // so no multiplier gives 0x80000061
+        data[0x14 + 0xC + 3] = 0x80;
+        let sarc = Sarc::new(&data).unwrap();
+        assert_eq!(sarc.guess_hash_multiplier(), None);
+    }
+
+    #[test]
+    fn yaz0_oversized() {
+        // A header claiming 4 GiB of decompressed data, with nothing after it
+        let mut data = b"Yaz0\xFF\xFF\xFF\xFF".to_vec();
+        data.resize(0x20, 0);
+        assert!(matches!(Sarc::new(&data), Err(super::SarcError::Yaz0Error)));
+    }
+
+    #[test]
+    fn yaz0_nested() {
+        let data = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"data".to_vec())
+            .write_to_bytes_compressed(CompressionLevel::Fast)
+            .unwrap();
+        assert!(Sarc::new(&data).is_ok());
+        // Only one layer of compression is removed
+        let nested = crate::yaz0::compress(&data, CompressionLevel::Fast);
+        assert!(Sarc::new(&nested).is_err());
+        assert!(Sarc::try_both_endians(&nested).is_err());
+    }
+
+    #[test]
+    fn file_eq_ignores_multiplier() {
+        let write = |multiplier| {
+            let mut writer = SarcWriter::new(Endian::Little);
+            writer.set_hash_multiplier(multiplier).unwrap();
+            writer.add_file("Actor/Pack/Test.sbactorpack", b"data".to_vec());
+            writer.write_to_bytes().unwrap()
+        };
+        let (first, second) = (write(0x65), write(0x1F));
+        let (first, second) = (Sarc::new(&first).unwrap(), Sarc::new(&second).unwrap());
+        let (file1, file2) = (first.file_at(0).unwrap(), second.file_at(0).unwrap());
+        assert_ne!(file1.name_hash, file2.name_hash);
+        assert_eq!(file1, file2);
+        assert!(Sarc::are_files_equal(&first, &second));
+    }
+
+    #[test]
+    fn parse_concatenated() {
+        let first = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"first".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        let second = SarcWriter::new(Endian::Big)
+            .with_file("b.bin", b"second".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        let stream = [first.as_slice(), second.as_slice()].concat();
+        let (sarc, size) = Sarc::new_from_prefix(&stream).unwrap();
+        assert_eq!(size, first.len());
+        assert_eq!(sarc.get_file("a.bin").unwrap().unwrap().data, b"first");
+        let (sarc, size) = Sarc::new_from_prefix(&stream[size..]).unwrap();
+        assert_eq!(size, second.len());
+        assert_eq!(sarc.get_file("b.bin").unwrap().unwrap().data, b"second");
+    }
+
+    /// Rewrites the name offset of each entry of a little endian archive
+    fn patch_name_offsets(data: &mut [u8], patch: impl Fn(usize, u32) -> u32) {
+        let num_files = u16::from_le_bytes([data[0x1A], data[0x1B]]) as usize;
+        for i in 0..num_files {
+            let pos = 0x14 + 0xC + 0x10 * i + 4;
+            let offset = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+            data[pos..pos + 4].copy_from_slice(&patch(i, offset).to_le_bytes());
+        }
+    }
+
+    fn name_offset_archive() -> (Vec<u8>, Vec<String>) {
+        let data = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"first".to_vec())
+            .with_file("bb.bin", b"second".to_vec())
+            .with_file("ccc.bin", b"third".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        let names = Sarc::new(&data)
+            .unwrap()
+            .files()
+            .map(|file| file.name.unwrap().to_owned())
+            .collect();
+        (data, names)
+    }
+
+    #[test]
+    fn name_offset_mode_scaled() {
+        let (mut data, names) = name_offset_archive();
+        // Drop the flag, except for an entry with offset 0, and make the last
+        // entry nameless
+        patch_name_offsets(&mut data, |i, offset| match i {
+            2 => 0,
+            _ if offset & 0xFFFFFF == 0 => offset,
+            _ => offset & 0xFFFFFF,
+        });
+        let sarc = Sarc::new(&data).unwrap();
+        let read: Vec<_> = sarc.files().map(|file| file.name).collect();
+        assert_eq!(
+            read,
+            [Some(names[0].as_str()), Some(names[1].as_str()), None]
+        );
+        assert_eq!(sarc.unnamed_count(), 1);
+        assert_eq!(
+            sarc.get_file(&names[1]).unwrap().unwrap().data,
+            sarc.file_at(1).unwrap().data
+        );
+    }
+
+    #[test]
+    fn name_offset_mode_absolute() {
+        let (mut data, names) = name_offset_archive();
+        // Unscale the offsets, keeping the flag for offset 0 so that it is
+        // not read as nameless, and make the last entry nameless
+        patch_name_offsets(&mut data, |i, offset| match (i, offset & 0xFFFFFF) {
+            (2, _) => 0,
+            (_, 0) => 1 << 24,
+            (_, scaled) => scaled * 4,
+        });
+        let mut sarc = Sarc::new(&data).unwrap();
+        sarc.set_name_offset_mode(NameOffsetMode::Absolute);
+        let read: Vec<_> = sarc.files().map(|file| file.name).collect();
+        assert_eq!(
+            read,
+            [Some(names[0].as_str()), Some(names[1].as_str()), None]
+        );
+        assert_eq!(sarc.unnamed_count(), 1);
+        assert_eq!(
+            sarc.get_file(&names[0]).unwrap().unwrap().data,
+            sarc.file_at(0).unwrap().data
+        );
+        assert!(sarc.get_file(&names[2]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_name_flag() {
+        let mut data = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"data".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        // Top byte of the first entry's name offset
+        data[0x14 + 0xC + 7] = 2;
+        let sarc = Sarc::new(&data).unwrap();
+        assert_eq!(sarc.file_at(0).unwrap().name, Some("a.bin"));
+        assert_eq!(sarc.named_count(), 1);
+        assert!(Sarc::new_validated(&data).is_err());
+    }
+
+    #[test]
+    fn name_offset_out_of_range() {
+        let mut data = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"data".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        // Largest name offset, keeping the flag
+        data[0x14 + 0xC + 4..0x14 + 0xC + 7].copy_from_slice(&[0xFF; 3]);
+        let sarc = Sarc::new(&data).unwrap();
+        assert!(matches!(
+            sarc.file_at(0),
+            Err(super::SarcError::InvalidData(..))
+        ));
+        assert!(sarc.file_at_lossy(0).is_err());
+        assert_eq!(sarc.files().count(), 0);
+    }
+
+    #[test]
+    fn data_offset_overflow() {
+        let mut data = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"data".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        // Data offset in the header and data end of the first entry
+        data[0xC..0x10].copy_from_slice(&(u32::MAX - 3).to_le_bytes());
+        data[0x14 + 0xC + 0xC..0x14 + 0xC + 0x10].copy_from_slice(&u32::MAX.to_le_bytes());
+        let sarc = Sarc::new(&data).unwrap();
+        assert!(matches!(
+            sarc.file_at(0),
+            Err(super::SarcError::InvalidData(..))
+        ));
+        assert!(sarc.manifest().is_empty());
+        assert!(Sarc::new_validated(&data).is_err());
+        assert_eq!(
+            Sarc::try_both_endians(&data).unwrap().endian(),
+            Endian::Little
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let mut writer = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"data".to_vec())
+            .with_file("b.bin", b"other data".to_vec())
+            .with_file("c.bin", b"data".to_vec());
+        writer.set_dedup(true);
+        let data = writer.write_to_bytes().unwrap();
+        // Deduplicated files share their data range
+        let sarc = Sarc::new_validated(&data).unwrap();
+        let (a, c) = (sarc.get_file("a.bin"), sarc.get_file("c.bin"));
+        assert_eq!(
+            a.unwrap().unwrap().data.as_ptr(),
+            c.unwrap().unwrap().data.as_ptr()
+        );
+
+        let entry = |i: usize| 0x14 + 0xC + 0x10 * i;
+        let patched = |pos: usize, value: u32| {
+            let mut data = data.clone();
+            data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+            data
+        };
+        let data_end =
+            u32::from_le_bytes(data[entry(0) + 0xC..entry(0) + 0x10].try_into().unwrap());
+        let invalid = [
+            // Data end past the end of the archive
+            patched(entry(0) + 0xC, data.len() as u32),
+            // Data begin after data end
+            patched(entry(0) + 8, data_end + 4),
+            // Unsorted name hashes
+            patched(entry(0), u32::MAX),
+            // Truncated buffer
+            data[..data.len() - 1].to_vec(),
+        ];
+        for data in invalid {
+            assert!(Sarc::new(&data).is_ok());
+            assert!(matches!(
+                Sarc::new_validated(&data),
+                Err(super::SarcError::InvalidData(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn extract_to() {
+        let dir = std::env::temp_dir().join(format!("sarc-extract-{}", std::process::id()));
+        let data = SarcWriter::new(Endian::Little)
+            .with_file("Dir/Sub/a.bin", b"nested".to_vec())
+            .with_file("b.bin", b"top".to_vec())
+            .with_file("nameless.bin", b"nameless".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        let index = Sarc::new(&data)
+            .unwrap()
+            .find_entry_index("nameless.bin")
+            .unwrap()
+            .unwrap();
+        let mut data = data;
+        let pos = 0x14 + 0xC + 0x10 * index + 4;
+        data[pos..pos + 4].fill(0);
+        Sarc::new(&data).unwrap().extract_to(&dir).unwrap();
+        assert_eq!(read(dir.join("Dir/Sub/a.bin")).unwrap(), b"nested");
+        assert_eq!(read(dir.join("b.bin")).unwrap(), b"top");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        for name in ["../escaped.bin", "Dir/../../escaped.bin", "/escaped.bin"] {
+            let data = SarcWriter::new(Endian::Little)
+                .with_file(name, b"data".to_vec())
+                .write_to_bytes()
+                .unwrap();
+            assert!(matches!(
+                Sarc::new(&data).unwrap().extract_to(dir.join("Dir")),
+                Err(super::SarcError::InvalidData(..))
+            ));
+        }
+        assert!(!dir.join("escaped.bin").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overhead_dedup() {
+        let mut writer = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"shared".to_vec())
+            .with_file("b.bin", b"unique data".to_vec())
+            .with_file("c.bin", b"shared".to_vec());
+        writer.set_dedup(true);
+        let data = writer.write_to_bytes().unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        let stats = sarc.overhead();
+        assert_eq!(stats.data_size, 6 + 11);
+        assert_eq!(sarc.total_data_size(), 6 + 11 + 6);
+        assert_eq!(
+            stats.header_size + stats.data_size + stats.padding_size,
+            sarc.loaded_size()
+        );
+    }
+
+    #[test]
+    fn get_files() {
+        let data = read("test/Dungeon119.pack").unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        let names = [
+            "Model/DgnMrgPrt_Dungeon119.sbfres",
+            "missing.bin",
+            "Map/CDungeon/Dungeon119/Dungeon119_Static.smubin",
+            "Actor/Pack/DgnMrgPrt_Dungeon119.sbactorpack",
+        ];
+        let files = sarc.get_files(&names);
+        assert_eq!(files.len(), names.len());
+        for (name, file) in names.iter().zip(files) {
+            assert_eq!(file.unwrap(), sarc.get_file(name).unwrap());
+        }
+    }
+
+    #[test]
+    fn get_colliding_files() {
+        // "aaseqa.bin" and "bxaaac.bin" have the same hash with the default multiplier
+        let mut writer = SarcWriter::new(Endian::Little);
+        writer
+            .files
+            .insert("aaseqa.bin".to_owned(), b"first".to_vec().into());
+        writer
+            .files
+            .insert("bxaaac.bin".to_owned(), b"second".to_vec().into());
+        let data = writer.write_to_bytes().unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        assert_eq!(sarc.get_file("aaseqa.bin").unwrap().unwrap().data, b"first");
+        assert_eq!(
+            sarc.get_file("bxaaac.bin").unwrap().unwrap().data,
+            b"second"
+        );
+        assert!(sarc.get_file("missing.bin").unwrap().is_none());
+    }
 }
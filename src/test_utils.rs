@@ -0,0 +1,118 @@
+//! Helpers for testing SARC tooling.
+use crate::parse::SarcError;
+use crate::writer::SarcWriteError;
+use crate::*;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+/// An enum representing all possible failures of [`verify_roundtrip`]
+pub enum RoundtripError {
+    /// The original or rebuilt archive could not be read
+    #[error(transparent)]
+    ReadError(#[from] SarcError),
+    /// The archive could not be rebuilt
+    #[error(transparent)]
+    WriteError(#[from] SarcWriteError),
+    /// A file is missing from the rebuilt archive
+    #[error("File {0} is missing after the roundtrip")]
+    MissingFileError(String),
+    /// A file was added to the rebuilt archive
+    #[error("File {0} was added by the roundtrip")]
+    ExtraFileError(String),
+    /// The rebuilt archive has a different number of entries, e.g. because
+    /// nameless entries cannot be rebuilt
+    #[error("Expected {expected} files after the roundtrip, found {found}")]
+    FileCountError {
+        /// Number of entries in the original archive
+        expected: usize,
+        /// Number of entries in the rebuilt archive
+        found: usize,
+    },
+    /// A file's stored name hash changed in the rebuilt archive
+    #[error("File {0} has a different name hash after the roundtrip")]
+    HashMismatchError(String),
+    /// A file's data changed in the rebuilt archive
+    #[error("File {name} differs after the roundtrip at byte {offset:#x}")]
+    MismatchError {
+        /// Name of the file
+        name: String,
+        /// Offset of the first differing byte
+        offset: usize,
+    },
+}
+
+/// Parses an archive, rebuilds it with [`SarcWriter::from_sarc`] and parses
+/// the result again, then checks that both archives contain the same number of
+/// entries and the same files, with the same name hashes and data. Archives
+/// with nameless entries always fail, since the writer cannot rebuild them.
+/// Returns an error describing the first mismatch.
+pub fn verify_roundtrip(data: &[u8]) -> Result<(), RoundtripError> {
+    let sarc = Sarc::new(data)?;
+    let new_data = SarcWriter::from_sarc(&sarc).write_to_bytes()?;
+    let new_sarc = Sarc::new(new_data)?;
+    for file in sarc.try_files() {
+        let file = file?;
+        let name = match file.name {
+            Some(name) => name,
+            None => continue,
+        };
+        let new_file = new_sarc
+            .get_file(name)?
+            .ok_or_else(|| RoundtripError::MissingFileError(name.to_owned()))?;
+        if new_file.name_hash != file.name_hash {
+            return Err(RoundtripError::HashMismatchError(name.to_owned()));
+        }
+        if new_file.data != file.data {
+            let offset = file
+                .data
+                .iter()
+                .zip(new_file.data)
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| file.data.len().min(new_file.data.len()));
+            return Err(RoundtripError::MismatchError {
+                name: name.to_owned(),
+                offset,
+            });
+        }
+    }
+    if let Some(name) = sarc.diff(&new_sarc).only_in_other.into_iter().next() {
+        return Err(RoundtripError::ExtraFileError(name));
+    }
+    if new_sarc.file_count() != sarc.file_count() {
+        return Err(RoundtripError::FileCountError {
+            expected: sarc.file_count(),
+            found: new_sarc.file_count(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_roundtrip, RoundtripError};
+    use crate::{Endian, SarcWriter};
+
+    #[test]
+    fn verify_roundtrip_files() {
+        let data = std::fs::read("test/Dungeon119.pack").unwrap();
+        verify_roundtrip(&data).unwrap();
+    }
+
+    #[test]
+    fn verify_roundtrip_nameless() {
+        let mut data = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"first".to_vec())
+            .with_file("b.bin", b"second".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        // Clear the name offset of the first entry to make it nameless
+        data[0x14 + 0xC + 4..0x14 + 0xC + 8].copy_from_slice(&[0; 4]);
+        assert!(matches!(
+            verify_roundtrip(&data),
+            Err(RoundtripError::FileCountError {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
@@ -1,17 +1,23 @@
 use crate::*;
 use binread::BinReaderExt;
 use cached::proc_macro::cached;
+use derivative::*;
 use indexmap::IndexMap;
 use num::ToPrimitive;
 use serde::Deserialize;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 use thiserror::Error;
 
 const FACTORY_INFO: &str = include_str!("../data/botw_resource_factory_info.tsv");
 const AGLENV_INFO: &str = include_str!("../data/aglenv_file_info.json");
 
 type Result<T> = core::result::Result<T, SarcWriteError>;
+type AlignmentObserver = Arc<Mutex<dyn FnMut(&str, usize) + Send>>;
+type AlignmentFn = Arc<dyn Fn(&str, &[u8]) -> Option<usize> + Send + Sync>;
 
 impl BinWrite for Endian {
     fn write_options<W: Write>(
@@ -31,6 +37,22 @@ impl BinWrite for Endian {
 pub enum SarcWriteError {
     #[error("{0} is not a valid alignment")]
     InvalidAlignmentError(usize),
+    #[error("{0} is not a valid hash multiplier")]
+    InvalidHashMultiplierError(u32),
+    #[error("A file named {0} already exists")]
+    FileExistsError(String),
+    #[error("No file named {0} exists")]
+    FileNotFoundError(String),
+    #[error("Symbolic links are not supported: {}", .0.display())]
+    SymlinkError(PathBuf),
+    #[error("File path is not valid UTF-8: {}", .0.display())]
+    InvalidPathError(PathBuf),
+    #[error("Archive size {0:#x} does not fit in 32-bit offsets")]
+    ArchiveTooLarge(usize),
+    #[error("{0} files exceed the limit of {} files per archive", MAX_FILE_COUNT)]
+    TooManyFiles(usize),
+    #[error("Name table size {0:#x} is too large for SFAT name offsets")]
+    NameTableTooLarge(usize),
     #[error(transparent)]
     IOError(#[from] std::io::Error),
 }
@@ -71,78 +93,471 @@ fn get_agl_env_alignment_requirements() -> Vec<(String, usize)> {
         .collect()
 }
 
+/// Alignment requirements applied to known file types unless overridden with
+/// [`SarcWriter::add_alignment_requirement`]. Some of them depend on the
+/// endianness, so they are looked up for the endianness being written.
+fn get_default_alignments(endian: Endian) -> &'static HashMap<String, usize> {
+    static BIG: OnceLock<HashMap<String, usize>> = OnceLock::new();
+    static LITTLE: OnceLock<HashMap<String, usize>> = OnceLock::new();
+    match endian {
+        Endian::Big => BIG.get_or_init(|| build_default_alignments(endian)),
+        Endian::Little => LITTLE.get_or_init(|| build_default_alignments(endian)),
+    }
+}
+
+fn build_default_alignments(endian: Endian) -> HashMap<String, usize> {
+    let mut alignments: HashMap<String, usize> =
+        get_agl_env_alignment_requirements().into_iter().collect();
+    for (ext, alignment) in [
+        ("ksky", 8),
+        ("bksky", 8),
+        ("gtx", 0x2000),
+        ("sharcb", 0x1000),
+        ("sharc", 0x1000),
+        ("baglmf", 0x80),
+        (
+            "bffnt",
+            match endian {
+                Endian::Big => 0x2000,
+                Endian::Little => 0x1000,
+            },
+        ),
+    ] {
+        alignments.insert(ext.to_owned(), alignment);
+    }
+    alignments
+}
+
+/// Where everything goes in an archive, computed before anything is written
+struct Layout {
+    /// Index of the file whose data each file points to, which is the file
+    /// itself unless its data is deduplicated
+    sources: Vec<usize>,
+    /// Offset of each file's data, relative to the data section
+    data_offsets: Vec<usize>,
+    /// End of the name table
+    names_end: usize,
+    /// Start of the data section
+    data_offset: usize,
+    file_size: usize,
+}
+
+#[inline(always)]
+fn write_padding<W: Write>(writer: &mut W, byte: u8, len: usize) -> std::io::Result<()> {
+    std::io::copy(&mut std::io::repeat(byte).take(len as u64), writer)?;
+    Ok(())
+}
+
+/// What [`SarcWriter::merge_from`] does when a file with the same name already
+/// exists in the writer
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MergePolicy {
+    /// Replace the existing file's data
+    Overwrite,
+    /// Keep the existing file's data
+    Skip,
+    /// Fail without adding any file
+    Error,
+}
+
 /// A simple SARC archive writer
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub struct SarcWriter {
+#[derive(Derivative)]
+#[derivative(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SarcWriter<'a> {
     endian: Endian,
     legacy: bool,
+    version: u16,
     hash_multiplier: u32,
     min_alignment: usize,
+    first_file_alignment: usize,
+    name_alignment: usize,
+    file_size_alignment: usize,
+    padding_byte: u8,
+    dedup: bool,
+    sort: bool,
+    use_default_alignments: bool,
     alignment_map: HashMap<String, usize>,
+    file_alignments: HashMap<String, usize>,
+    #[derivative(Debug = "ignore", PartialEq = "ignore")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    alignment_observer: Option<AlignmentObserver>,
+    #[derivative(Debug = "ignore", PartialEq = "ignore")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    alignment_fn: Option<AlignmentFn>,
     /// Files to be written.
-    pub files: IndexMap<String, Vec<u8>>,
+    pub files: IndexMap<String, Cow<'a, [u8]>>,
+}
+
+impl Default for SarcWriter<'_> {
+    /// Creates a little endian writer, as used on Switch
+    fn default() -> Self {
+        SarcWriter::new(Endian::Little)
+    }
 }
 
-impl SarcWriter {
+impl FromIterator<(String, Vec<u8>)> for SarcWriter<'_> {
+    /// Creates a little endian writer with the given files. Use
+    /// [`SarcWriter::set_endian`] to change the endianness.
+    fn from_iter<I: IntoIterator<Item = (String, Vec<u8>)>>(iter: I) -> Self {
+        let mut writer = SarcWriter::default();
+        writer.extend(iter);
+        writer
+    }
+}
+
+impl Extend<(String, Vec<u8>)> for SarcWriter<'_> {
+    /// Adds files, replacing the data of files that already exist
+    fn extend<I: IntoIterator<Item = (String, Vec<u8>)>>(&mut self, iter: I) {
+        self.files
+            .extend(iter.into_iter().map(|(name, data)| (name, data.into())));
+    }
+}
+
+impl<'a> SarcWriter<'a> {
     /// A simple SARC archive writer
-    pub fn new(endian: Endian) -> SarcWriter {
+    pub fn new(endian: Endian) -> SarcWriter<'a> {
         SarcWriter {
             endian,
             legacy: false,
-            hash_multiplier: 0x65,
+            version: SARC_VERSION,
+            hash_multiplier: DEFAULT_HASH_MULTIPLIER,
             alignment_map: HashMap::new(),
+            file_alignments: HashMap::new(),
             files: IndexMap::new(),
             min_alignment: 4,
+            first_file_alignment: 1,
+            name_alignment: NAME_OFFSET_SCALE,
+            file_size_alignment: 1,
+            padding_byte: 0,
+            dedup: false,
+            sort: true,
+            use_default_alignments: true,
+            alignment_observer: None,
+            alignment_fn: None,
+        }
+    }
+
+    /// Creates a new SARC writer with room for `capacity` files
+    pub fn with_capacity(endian: Endian, capacity: usize) -> SarcWriter<'a> {
+        SarcWriter {
+            files: IndexMap::with_capacity(capacity),
+            ..SarcWriter::new(endian)
+        }
+    }
+
+    /// Creates a new SARC writer with the given alignment requirements by file
+    /// extension, e.g. as returned by [`SarcWriter::alignment_requirements`].
+    /// Fails if any of the alignments is not a power of 2.
+    pub fn with_alignments(endian: Endian, map: HashMap<String, usize>) -> Result<SarcWriter<'a>> {
+        if let Some(&alignment) = map.values().find(|a| !is_valid_alignment(**a)) {
+            return Err(SarcWriteError::InvalidAlignmentError(alignment));
         }
+        Ok(SarcWriter {
+            alignment_map: map,
+            ..SarcWriter::new(endian)
+        })
     }
 
     /// Creates a new SARC writer by taking attributes and files
-    /// from an existing SARC reader
-    pub fn from_sarc(sarc: &Sarc) -> SarcWriter {
+    /// from an existing SARC reader. The endianness, version, hash multiplier
+    /// and padding byte are preserved. Nameless entries cannot be stored in a
+    /// writer, so they are skipped.
+    ///
+    /// The file data is copied, so the writer does not borrow the archive. Use
+    /// [`SarcWriter::from_sarc_shared`] to avoid the copies.
+    pub fn from_sarc(sarc: &Sarc) -> SarcWriter<'static> {
+        SarcWriter::from_sarc_shared(sarc).into_owned()
+    }
+
+    /// Creates a new SARC writer from an existing SARC reader like
+    /// [`SarcWriter::from_sarc`], but borrows the file data from the archive
+    /// rather than copying it, so only files that are inserted or replaced
+    /// afterwards need to be owned. Use [`SarcWriter::into_owned`] to detach
+    /// the writer from the archive.
+    pub fn from_sarc_shared(sarc: &'a Sarc) -> SarcWriter<'a> {
         SarcWriter {
-            endian: sarc.endian(),
-            legacy: false,
-            hash_multiplier: 0x65,
-            alignment_map: HashMap::new(),
+            version: sarc.version(),
+            hash_multiplier: sarc.hash_multiplier(),
             files: sarc
                 .files()
-                .filter_map(|f| f.name.map(|name| (name.to_owned(), f.data.to_vec())))
+                .filter_map(|f| f.name.map(|name| (name.to_owned(), Cow::Borrowed(f.data))))
                 .collect(),
-            min_alignment: sarc.guess_min_alignment(),
+            min_alignment: sarc.guess_min_alignment().unwrap_or(4),
+            padding_byte: sarc.guess_padding_byte().unwrap_or(0),
+            ..SarcWriter::new(sarc.endian())
         }
     }
 
-    /// Write a SARC archive to an in-memory buffer using the specified endianness.
-    /// Default alignment requirements may be automatically added.
-    pub fn write_to_bytes(&mut self) -> Result<Vec<u8>> {
-        let est_size: usize = 0x14
-            + 0x0C
+    /// Creates a new SARC writer that extends an existing archive, keeping its
+    /// endianness, version and hash multiplier. The data of the existing files
+    /// is borrowed like with [`SarcWriter::from_sarc_shared`], so only the added
+    /// files need to be owned.
+    pub fn append_to(base: &'a Sarc) -> SarcWriter<'a> {
+        SarcWriter::from_sarc_shared(base)
+    }
+
+    /// Copies any borrowed file data, so that the writer no longer borrows
+    /// from the archive it was created from
+    pub fn into_owned(self) -> SarcWriter<'static> {
+        SarcWriter {
+            endian: self.endian,
+            legacy: self.legacy,
+            version: self.version,
+            hash_multiplier: self.hash_multiplier,
+            min_alignment: self.min_alignment,
+            first_file_alignment: self.first_file_alignment,
+            name_alignment: self.name_alignment,
+            file_size_alignment: self.file_size_alignment,
+            padding_byte: self.padding_byte,
+            dedup: self.dedup,
+            sort: self.sort,
+            use_default_alignments: self.use_default_alignments,
+            alignment_map: self.alignment_map,
+            file_alignments: self.file_alignments,
+            alignment_observer: self.alignment_observer,
+            alignment_fn: self.alignment_fn,
+            files: self
+                .files
+                .into_iter()
+                .map(|(name, data)| (name, Cow::Owned(data.into_owned())))
+                .collect(),
+        }
+    }
+
+    /// Creates a new SARC writer from all files in a directory tree. Each file
+    /// is named after its path relative to `dir`, using forward slashes.
+    /// Directories themselves are not stored since SARC has no directory
+    /// entries, and symbolic links are rejected with an error.
+    pub fn from_directory<P: AsRef<Path>>(dir: P, endian: Endian) -> Result<SarcWriter<'a>> {
+        let dir = dir.as_ref();
+        let mut writer = SarcWriter::new(endian);
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in std::fs::read_dir(&current)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let path = entry.path();
+                if file_type.is_symlink() {
+                    return Err(SarcWriteError::SymlinkError(path));
+                } else if file_type.is_dir() {
+                    pending.push(path);
+                } else {
+                    let name = path
+                        .strip_prefix(dir)
+                        .ok()
+                        .and_then(|relative| relative.to_str())
+                        .ok_or_else(|| SarcWriteError::InvalidPathError(path.clone()))?
+                        .replace(std::path::MAIN_SEPARATOR, "/");
+                    let data = std::fs::read(&path)?;
+                    writer.files.insert(name, data.into());
+                }
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Write a zstd compressed SARC archive to an in-memory buffer, using the
+    /// given zstd compression level (0 for the default).
+    /// Default alignment requirements are applied to known file types.
+    #[cfg(feature = "zstd")]
+    pub fn write_to_bytes_zstd(&mut self, level: i32) -> Result<Vec<u8>> {
+        Ok(zstd::encode_all(self.write_to_bytes()?.as_slice(), level)?)
+    }
+
+    /// Estimate the size of the written archive: the headers, names and file
+    /// data, without alignment padding
+    pub fn estimated_size(&self) -> usize {
+        0x14 + 0x0C
             + 0x8
             + self
                 .files
                 .iter()
-                .map(|(n, d)| 0x10 + align(n.len() + 1, 4) + d.len())
-                .sum::<usize>();
+                .map(|(n, d)| 0x10 + align(n.len() + 1, self.name_alignment) + d.len())
+                .sum::<usize>()
+    }
+
+    /// Write a SARC archive to an in-memory buffer using the specified endianness.
+    /// Default alignment requirements are applied to known file types.
+    pub fn write_to_bytes(&mut self) -> Result<Vec<u8>> {
+        let est_size = self.estimated_size();
         let mut buf: Vec<u8> = Vec::with_capacity((est_size as f32 * 1.5).to_usize().unwrap());
         self.write(&mut Cursor::new(&mut buf))?;
         Ok(buf)
     }
 
+    /// Write a SARC archive to a file.
+    /// Default alignment requirements are applied to known file types.
+    pub fn write_to_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write a Yaz0 compressed SARC archive to an in-memory buffer.
+    /// Default alignment requirements are applied to known file types.
+    pub fn write_to_bytes_compressed(&mut self, level: CompressionLevel) -> Result<Vec<u8>> {
+        Ok(yaz0::compress(&self.write_to_bytes()?, level))
+    }
+
     /// Write a SARC archive to a Write + Seek writer using the specified endianness.
-    /// Default alignment requirements may be automatically added.
+    /// Default alignment requirements are applied to known file types.
+    ///
+    /// The output is deterministic: files are sorted by name hash and then by
+    /// name, so the same set of names and data written with the same settings
+    /// (endianness, hash multiplier and alignments) always produces the same
+    /// bytes, regardless of the order in which the files were added
+    /// (unless sorting is disabled with [`SarcWriter::set_sort`]).
     pub fn write<W: Write + Seek>(&mut self, writer: &mut W) -> Result<()> {
+        writer.seek(SeekFrom::Start(0))?;
+        self.write_streaming(writer)
+    }
+
+    /// Write a SARC archive to a writer that does not need to support seeking,
+    /// such as stdout or a socket. The layout of the archive is computed before
+    /// anything is written, so the output is the same as with
+    /// [`SarcWriter::write`].
+    pub fn write_streaming<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        let layout = self.compute_layout()?;
+        self.write_layout(&layout, writer)
+    }
+
+    /// Sort the files and compute where their data goes
+    /// Check the settings again, since a deserialized writer bypasses the
+    /// validation of the setters
+    fn validate_settings(&self) -> Result<()> {
+        if self.hash_multiplier == 0 {
+            return Err(SarcWriteError::InvalidHashMultiplierError(
+                self.hash_multiplier,
+            ));
+        }
+        if self.name_alignment % NAME_OFFSET_SCALE != 0 {
+            return Err(SarcWriteError::InvalidAlignmentError(self.name_alignment));
+        }
+        [
+            self.min_alignment,
+            self.first_file_alignment,
+            self.name_alignment,
+            self.file_size_alignment,
+        ]
+        .into_iter()
+        .chain(self.alignment_map.values().copied())
+        .chain(self.file_alignments.values().copied())
+        .find(|alignment| !is_valid_alignment(*alignment))
+        .map_or(Ok(()), |alignment| {
+            Err(SarcWriteError::InvalidAlignmentError(alignment))
+        })
+    }
+
+    fn compute_layout(&mut self) -> Result<Layout> {
+        self.validate_settings()?;
+        if self.files.len() > MAX_FILE_COUNT {
+            return Err(SarcWriteError::TooManyFiles(self.files.len()));
+        }
+        let multiplier = self.hash_multiplier;
+        if self.sort {
+            self.files.sort_by(move |name, _, name2, _| {
+                Ord::cmp(&hash_name(multiplier, name), &hash_name(multiplier, name2))
+                    .then_with(|| name.cmp(name2))
+            });
+        }
+
+        let mut alignments: Vec<usize> = Vec::with_capacity(self.files.len());
+        for (i, (name, data)) in self.files.iter().enumerate() {
+            let mut alignment = self.get_alignment_for_file(name, data)?;
+            if i == 0 {
+                alignment = num::integer::lcm(alignment, self.first_file_alignment);
+            }
+            alignments.push(alignment);
+        }
+
+        // With deduplication, files point to the first file with identical
+        // data, and its alignment has to satisfy every file sharing it.
+        let sources: Vec<usize> = if self.dedup {
+            let mut seen: HashMap<&[u8], usize> = HashMap::new();
+            self.files
+                .values()
+                .enumerate()
+                .map(|(i, data)| *seen.entry(data.as_ref()).or_insert(i))
+                .collect()
+        } else {
+            (0..self.files.len()).collect()
+        };
+        for (i, source) in sources.iter().enumerate() {
+            if *source != i {
+                alignments[*source] = num::integer::lcm(alignments[*source], alignments[i]);
+            }
+        }
+
+        let mut rel_data_offset = 0;
+        let mut data_offsets: Vec<usize> = Vec::with_capacity(self.files.len());
+        for (i, (name, data)) in self.files.iter().enumerate() {
+            let alignment = alignments[sources[i]];
+            if let Some(observer) = &self.alignment_observer {
+                if let Ok(mut observer) = observer.lock() {
+                    (*observer)(name.as_str(), alignment);
+                }
+            }
+
+            let offset = if sources[i] == i {
+                let offset = align(rel_data_offset, alignment);
+                rel_data_offset = offset + data.len();
+                offset
+            } else {
+                data_offsets[sources[i]]
+            };
+            data_offsets.push(offset);
+        }
+
+        let name_sizes = self
+            .files
+            .keys()
+            .map(|name| align(name.len() + 1, self.name_alignment));
+        let names_size: usize = name_sizes.clone().sum();
+        // Only the start of each name has to fit in an SFAT entry
+        let last_name_offset = names_size - name_sizes.last().unwrap_or(0);
+        if last_name_offset / NAME_OFFSET_SCALE > MAX_NAME_OFFSET {
+            return Err(SarcWriteError::NameTableTooLarge(names_size));
+        }
+        let names_end = 0x14 + 0x0C + 0x10 * self.files.len() + 0x8 + names_size;
+        let required_alignment = alignments
+            .iter()
+            .fold(1, |acc, alignment| num::integer::lcm(acc, *alignment));
+        let data_offset = align(names_end, required_alignment);
+        // Every offset in the archive is at most the file size
+        let file_size = align(data_offset + rel_data_offset, self.file_size_alignment);
+        if file_size > u32::MAX as usize {
+            return Err(SarcWriteError::ArchiveTooLarge(file_size));
+        }
+        Ok(Layout {
+            sources,
+            data_offsets,
+            names_end,
+            data_offset,
+            file_size,
+        })
+    }
+
+    /// Write the archive sequentially, following a layout computed by
+    /// [`SarcWriter::compute_layout`]
+    fn write_layout<W: Write>(&self, layout: &Layout, writer: &mut W) -> Result<()> {
         let mut opts = binwrite::WriterOption::default();
         opts.endian = match self.endian {
             Endian::Big => binwrite::Endian::Big,
             Endian::Little => binwrite::Endian::Little,
         };
-        let multiplier = self.hash_multiplier;
 
-        self.files.sort_by(move |name, _, name2, _| {
-            Ord::cmp(&hash_name(multiplier, name), &hash_name(multiplier, name2))
-        });
-
-        writer.seek(SeekFrom::Start(0x14))?;
+        ResHeader {
+            magic: SARC_MAGIC,
+            header_size: 0x14,
+            bom: self.endian,
+            file_size: layout.file_size as u32,
+            data_offset: layout.data_offset as u32,
+            version: self.version,
+            reserved: 0,
+        }
+        .write_options(writer, &opts)?;
         ResFatHeader {
             magic: SFAT_MAGIC,
             header_size: 0x0C,
@@ -151,28 +566,16 @@ impl SarcWriter {
         }
         .write_options(writer, &opts)?;
 
-        self.add_default_alignments();
-        let mut alignments: Vec<usize> = Vec::with_capacity(self.files.len());
-
-        {
-            let mut rel_string_offset = 0;
-            let mut rel_data_offset = 0;
-            for (name, data) in self.files.iter() {
-                let alignment = self.get_alignment_for_file(name, data);
-                alignments.push(alignment);
-
-                let offset = align(rel_data_offset, alignment);
-                ResFatEntry {
-                    name_hash: hash_name(self.hash_multiplier, name),
-                    rel_name_opt_offset: 1 << 24 | (rel_string_offset / 4),
-                    data_begin: offset as u32,
-                    data_end: (offset + data.len()) as u32,
-                }
-                .write_options(writer, &opts)?;
-
-                rel_data_offset = offset + data.len();
-                rel_string_offset += align(name.len() + 1, 4) as u32;
+        let mut rel_string_offset = 0;
+        for ((name, data), offset) in self.files.iter().zip(layout.data_offsets.iter()) {
+            ResFatEntry {
+                name_hash: hash_name(self.hash_multiplier, name),
+                rel_name_opt_offset: 1 << 24 | (rel_string_offset / NAME_OFFSET_SCALE as u32),
+                data_begin: *offset as u32,
+                data_end: (offset + data.len()) as u32,
             }
+            .write_options(writer, &opts)?;
+            rel_string_offset += align(name.len() + 1, self.name_alignment) as u32;
         }
 
         ResFntHeader {
@@ -181,41 +584,164 @@ impl SarcWriter {
             reserved: 0,
         }
         .write_options(writer, &opts)?;
-        for (name, _) in self.files.iter() {
-            name.write(writer)?;
-            0u8.write(writer)?;
-            let pos = writer.stream_position()? as usize;
-            writer.seek(SeekFrom::Start(align(pos, 4) as u64))?;
+        for name in self.files.keys() {
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&[0])?;
+            write_padding(
+                writer,
+                0,
+                align(name.len() + 1, self.name_alignment) - (name.len() + 1),
+            )?;
         }
 
-        let required_alignment = alignments
+        write_padding(
+            writer,
+            self.padding_byte,
+            layout.data_offset - layout.names_end,
+        )?;
+        let mut pos = layout.data_offset;
+        for (i, data) in self.files.values().enumerate() {
+            if layout.sources[i] != i {
+                continue;
+            }
+            let offset = layout.data_offset + layout.data_offsets[i];
+            write_padding(writer, self.padding_byte, offset - pos)?;
+            writer.write_all(data)?;
+            pos = offset + data.len();
+        }
+        write_padding(writer, self.padding_byte, layout.file_size - pos)?;
+        Ok(())
+    }
+
+    /// Add a file. If a file with the same name already exists, its data is
+    /// replaced.
+    pub fn add_file(&mut self, name: impl Into<String>, data: impl Into<Cow<'a, [u8]>>) {
+        self.files.insert(name.into(), data.into());
+    }
+
+    /// Replace the data of an existing file, returning its previous data.
+    /// Unlike [`SarcWriter::add_file`], this fails instead of adding the file
+    /// if it does not exist.
+    pub fn update_file(&mut self, name: &str, data: impl Into<Cow<'a, [u8]>>) -> Result<Vec<u8>> {
+        match self.files.get_mut(name) {
+            Some(old) => Ok(std::mem::replace(old, data.into()).into_owned()),
+            None => Err(SarcWriteError::FileNotFoundError(name.to_owned())),
+        }
+    }
+
+    /// Add a file by reading its data to the end of a stream. If a file with
+    /// the same name already exists, its data is replaced.
+    pub fn add_file_from_reader<R: Read>(
+        &mut self,
+        name: impl Into<String>,
+        reader: &mut R,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.add_file(name, data);
+        Ok(())
+    }
+
+    /// Returns an iterator over the names and data of the files to be written
+    pub fn files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
             .iter()
-            .fold(1, |acc, alignment| num::integer::lcm(acc, *alignment));
-        let pos = writer.stream_position()? as usize;
-        writer.seek(SeekFrom::Start(align(pos, required_alignment) as u64))?;
-        let data_offset_begin = writer.stream_position()? as u32;
-        for ((_, data), alignment) in self.files.iter().zip(alignments.iter()) {
-            let pos = writer.stream_position()? as usize;
-            writer.seek(SeekFrom::Start(align(pos, *alignment) as u64))?;
-            data.write(writer)?;
+            .map(|(name, data)| (name.as_str(), data.as_ref()))
+    }
+
+    /// Returns the number of files to be written
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if there are no files to be written
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Remove all files, keeping every setting (including per-file
+    /// alignments) so the writer can be reused for another archive
+    pub fn clear(&mut self) {
+        self.files.clear()
+    }
+
+    /// Remove a file, returning its data if it existed
+    pub fn remove_file(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.files.shift_remove(name).map(Cow::into_owned)
+    }
+
+    /// Find pairs of distinct file names that have the same hash under the
+    /// current multiplier. This crate's reader handles such collisions, but
+    /// readers that only binary search by hash (like the games' own) will not
+    /// be able to find all of the colliding files.
+    pub fn check_duplicates(&self) -> Vec<(String, String)> {
+        let mut hashes: Vec<(u32, &str)> = self
+            .files
+            .keys()
+            .map(|name| (hash_name(self.hash_multiplier, name), name.as_str()))
+            .collect();
+        hashes.sort_unstable();
+        let mut duplicates = vec![];
+        for (i, (hash, name)) in hashes.iter().enumerate() {
+            for (other_hash, other_name) in &hashes[i + 1..] {
+                if other_hash != hash {
+                    break;
+                }
+                duplicates.push((name.to_string(), other_name.to_string()));
+            }
         }
+        duplicates
+    }
 
-        let file_size = writer.stream_position()? as u32;
-        writer.seek(SeekFrom::Start(0))?;
-        ResHeader {
-            magic: SARC_MAGIC,
-            header_size: 0x14,
-            bom: self.endian,
-            file_size,
-            data_offset: data_offset_begin,
-            version: 0x0100,
-            reserved: 0,
+    /// Keep only the files for which the predicate returns true
+    pub fn retain<F: FnMut(&str, &[u8]) -> bool>(&mut self, mut f: F) {
+        self.files.retain(|name, data| f(name, data))
+    }
+
+    /// Rename a file. Returns whether a file named `old` existed, or an error if
+    /// another file is already named `new`. Since the name determines the hash,
+    /// the file will be sorted into a different position on the next write.
+    pub fn rename_file(&mut self, old: &str, new: &str) -> Result<bool> {
+        if !self.files.contains_key(old) {
+            return Ok(false);
+        }
+        if old != new && self.files.contains_key(new) {
+            return Err(SarcWriteError::FileExistsError(new.to_owned()));
+        }
+        if let Some(data) = self.files.shift_remove(old) {
+            self.files.insert(new.to_owned(), data);
+        }
+        Ok(true)
+    }
+
+    /// Copy the named files of an archive into the writer. `policy` decides
+    /// what happens to files that already exist; with [`MergePolicy::Error`],
+    /// nothing is added if any name conflicts.
+    pub fn merge_from(&mut self, sarc: &Sarc, policy: MergePolicy) -> Result<()> {
+        if policy == MergePolicy::Error {
+            if let Some(name) = sarc
+                .file_names()
+                .find(|name| self.files.contains_key(*name))
+            {
+                return Err(SarcWriteError::FileExistsError(name.to_owned()));
+            }
+        }
+        for file in sarc.files() {
+            if let Some(name) = file.name {
+                if policy == MergePolicy::Skip && self.files.contains_key(name) {
+                    continue;
+                }
+                self.files
+                    .insert(name.to_owned(), file.data.to_vec().into());
+            }
         }
-        .write_options(writer, &opts)?;
         Ok(())
     }
 
-    /// Add or modify a data alignment requirement for a file type. Set the alignment to 1 to revert.
+    /// Add or modify a data alignment requirement for a file type. This takes
+    /// precedence over the built-in requirement for the type, if any, so an
+    /// alignment of 1 disables the built-in requirement and leaves only the
+    /// minimum alignment and the requirements that do not depend on the type.
     ///
     /// # Arguments
     ///
@@ -229,37 +755,69 @@ impl SarcWriter {
         Ok(())
     }
 
-    fn add_default_alignments(&mut self) {
-        // This is perfectly sound because all of these alignments are powers
-        // of 2 and thus the calls cannot fail.
-        unsafe {
-            for (ext, alignment) in get_agl_env_alignment_requirements() {
-                self.add_alignment_requirement(ext, alignment)
-                    .unwrap_unchecked();
+    /// Add or modify a data alignment requirement for a single file, which is
+    /// combined with any other requirements for it. Set the alignment to 1 to
+    /// revert.
+    pub fn set_file_alignment(&mut self, name: &str, alignment: usize) -> Result<()> {
+        if !is_valid_alignment(alignment) {
+            return Err(SarcWriteError::InvalidAlignmentError(alignment));
+        }
+        self.file_alignments.insert(name.to_owned(), alignment);
+        Ok(())
+    }
+
+    /// Add or modify data alignment requirements for several file types at once.
+    /// Unlike [`SarcWriter::add_alignment_requirement`], this does not stop at the
+    /// first invalid alignment: every valid entry is applied and all invalid
+    /// entries are returned together.
+    pub fn try_set_alignments(
+        &mut self,
+        map: HashMap<String, usize>,
+    ) -> core::result::Result<(), Vec<(String, usize)>> {
+        let mut invalid = vec![];
+        for (ext, alignment) in map {
+            if is_valid_alignment(alignment) {
+                self.alignment_map.insert(ext, alignment);
+            } else {
+                invalid.push((ext, alignment));
             }
-            self.add_alignment_requirement("ksky".to_owned(), 8)
-                .unwrap_unchecked();
-            self.add_alignment_requirement("ksky".to_owned(), 8)
-                .unwrap_unchecked();
-            self.add_alignment_requirement("bksky".to_owned(), 8)
-                .unwrap_unchecked();
-            self.add_alignment_requirement("gtx".to_owned(), 0x2000)
-                .unwrap_unchecked();
-            self.add_alignment_requirement("sharcb".to_owned(), 0x1000)
-                .unwrap_unchecked();
-            self.add_alignment_requirement("sharc".to_owned(), 0x1000)
-                .unwrap_unchecked();
-            self.add_alignment_requirement("baglmf".to_owned(), 0x80)
-                .unwrap_unchecked();
-            self.add_alignment_requirement(
-                "bffnt".to_owned(),
-                match self.endian {
-                    Endian::Big => 0x2000,
-                    Endian::Little => 0x1000,
-                },
-            )
-            .unwrap_unchecked();
         }
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Returns the alignment requirements by file extension, in no particular
+    /// order. With `include_defaults`, the built-in requirements that apply
+    /// (unless disabled with [`SarcWriter::set_use_default_alignments`]) are
+    /// included for the writer's current endianness, except where overridden.
+    pub fn alignment_requirements(
+        &self,
+        include_defaults: bool,
+    ) -> impl Iterator<Item = (&str, usize)> + '_ {
+        let defaults: Option<&HashMap<String, usize>> =
+            if include_defaults && self.use_default_alignments {
+                Some(get_default_alignments(self.endian))
+            } else {
+                None
+            };
+        let defaults = defaults
+            .into_iter()
+            .flatten()
+            .filter(|(ext, _)| !self.alignment_map.contains_key(*ext));
+        self.alignment_map
+            .iter()
+            .chain(defaults)
+            .map(|(ext, alignment)| (ext.as_str(), *alignment))
+    }
+
+    /// Get the minimum data alignment. For writers created with
+    /// [`SarcWriter::from_sarc`], this is the alignment guessed from the
+    /// source archive.
+    pub fn min_alignment(&self) -> usize {
+        self.min_alignment
     }
 
     /// Set the minimum data alignment
@@ -271,21 +829,144 @@ impl SarcWriter {
         Ok(())
     }
 
+    /// Set an additional alignment for the first file only. Because the first
+    /// file starts the data section, this also aligns the archive's data offset,
+    /// which some streaming decompressors rely on. Set the alignment to 1 to revert.
+    pub fn set_first_file_alignment(&mut self, alignment: usize) -> Result<()> {
+        if !is_valid_alignment(alignment) {
+            return Err(SarcWriteError::InvalidAlignmentError(alignment));
+        }
+        self.first_file_alignment = alignment;
+        Ok(())
+    }
+
+    /// Set the alignment of the total archive size. The archive is padded at
+    /// the end to a multiple of it, which is reflected in the header's file
+    /// size. Set the alignment to 1 to revert.
+    pub fn set_file_size_alignment(&mut self, alignment: usize) -> Result<()> {
+        if !is_valid_alignment(alignment) {
+            return Err(SarcWriteError::InvalidAlignmentError(alignment));
+        }
+        self.file_size_alignment = alignment;
+        Ok(())
+    }
+
+    /// Set the byte used to fill the alignment gaps before and between file
+    /// data and at the end of the archive. The default is 0; some tools use
+    /// other values, which can be reproduced this way. Names are always padded
+    /// with zeros.
+    pub fn set_padding_byte(&mut self, byte: u8) {
+        self.padding_byte = byte
+    }
+
+    /// Set the alignment of names in the name table. The default is 4, which
+    /// is also the smallest possible value since name offsets are stored
+    /// divided by 4; other values must be multiples of it.
+    pub fn set_name_alignment(&mut self, alignment: usize) -> Result<()> {
+        if !is_valid_alignment(alignment) || alignment % NAME_OFFSET_SCALE != 0 {
+            return Err(SarcWriteError::InvalidAlignmentError(alignment));
+        }
+        self.name_alignment = alignment;
+        Ok(())
+    }
+
+    /// Set a callback that is invoked with the name and chosen data alignment of
+    /// each file while the archive is written. This is useful for tracing why a
+    /// file ended up with a particular alignment.
+    pub fn set_alignment_observer(&mut self, observer: impl FnMut(&str, usize) + Send + 'static) {
+        self.alignment_observer = Some(Arc::new(Mutex::new(observer)));
+    }
+
+    /// Set a function that decides the data alignment of files, e.g. for games
+    /// with different rules than BOTW. When it returns `Some`, the result
+    /// replaces the built-in alignment rules for that file and is only combined
+    /// with the minimum alignment. Returning an alignment that is not a power of
+    /// 2 makes writing fail.
+    pub fn set_alignment_fn(&mut self, f: Box<dyn Fn(&str, &[u8]) -> Option<usize> + Send + Sync>) {
+        self.alignment_fn = Some(Arc::from(f));
+    }
+
+    /// Set the format version written to the header. The default is `0x0100`,
+    /// the only version used by official archives; others can only be read
+    /// back with [`Sarc::new_any_version`].
+    pub fn set_version(&mut self, version: u16) {
+        self.version = version
+    }
+
+    /// Set the multiplier used to hash file names. The default is `0x65`; 0 is
+    /// rejected because hashes would then only depend on the last character.
+    pub fn set_hash_multiplier(&mut self, multiplier: u32) -> Result<()> {
+        if multiplier == 0 {
+            return Err(SarcWriteError::InvalidHashMultiplierError(multiplier));
+        }
+        self.hash_multiplier = multiplier;
+        Ok(())
+    }
+
+    /// Set whether files are sorted by name hash when writing. Enabled by
+    /// default. When disabled, files are written in the order they were added,
+    /// which is only meant for reproducing archives made by tools that did not
+    /// sort them. Such archives do not conform to the format: lookups by name,
+    /// including [`Sarc::get_file`], rely on the sorting and may fail.
+    pub fn set_sort(&mut self, value: bool) {
+        self.sort = value
+    }
+
+    /// Set whether files with identical data should share a single copy of
+    /// that data in the archive. Disabled by default.
+    pub fn set_dedup(&mut self, value: bool) {
+        self.dedup = value
+    }
+
+    /// Set whether the built-in alignment rules for known file types are
+    /// applied, which are tailored to BOTW. When disabled, files are only
+    /// aligned to the minimum alignment and the requirements added with
+    /// [`SarcWriter::add_alignment_requirement`] (and to 0x2000 for nested SARCs
+    /// in legacy mode). Enabled by default.
+    pub fn set_use_default_alignments(&mut self, value: bool) {
+        self.use_default_alignments = value
+    }
+
     /// Set whether to use legacy mode (for games without a BOTW-style
     /// resource system) for addtional alignment restrictions
     pub fn set_legacy_mode(&mut self, value: bool) {
         self.legacy = value
     }
 
-    /// Set the endianness
+    /// Set the endianness. Alignments that depend on the endianness are
+    /// determined when writing, so a writer created with
+    /// [`SarcWriter::from_sarc`] can be used to convert an archive.
     pub fn set_endian(&mut self, endian: Endian) {
         self.endian = endian
     }
 
+    /// Builder variant of [`SarcWriter::add_file`]
+    pub fn with_file(mut self, name: impl Into<String>, data: impl Into<Cow<'a, [u8]>>) -> Self {
+        self.add_file(name, data);
+        self
+    }
+
+    /// Builder variant of [`SarcWriter::set_min_alignment`]
+    pub fn with_min_alignment(mut self, alignment: usize) -> Result<Self> {
+        self.set_min_alignment(alignment)?;
+        Ok(self)
+    }
+
+    /// Builder variant of [`SarcWriter::set_legacy_mode`]
+    pub fn with_legacy_mode(mut self, value: bool) -> Self {
+        self.set_legacy_mode(value);
+        self
+    }
+
+    /// Builder variant of [`SarcWriter::set_endian`]
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.set_endian(endian);
+        self
+    }
+
     /// Checks if a data slice represents a SARC archive
     pub fn is_file_sarc(data: &[u8]) -> bool {
-        data.len() >= 0x20
-            && (&data[0..4] == b"SARC" || (&data[0..4] == b"Yaz0" && &data[0x11..0x15] == b"SARC"))
+        is_file_sarc(data)
     }
 
     fn get_alignment_for_new_binary_file(data: &[u8]) -> usize {
@@ -319,30 +1000,40 @@ impl SarcWriter {
         }
     }
 
-    fn get_alignment_for_file(&self, name: &str, data: &[u8]) -> usize {
-        let ext = match name.rfind('.') {
-            Some(idx) => &name[idx + 1..],
-            None => "",
-        };
+    fn get_alignment_for_file(&self, name: &str, data: &[u8]) -> Result<usize> {
         let mut alignment = self.min_alignment;
-        if let Some(requirement) = self.alignment_map.get(ext) {
+        if let Some(requirement) = self.file_alignments.get(name) {
+            alignment = num::integer::lcm(alignment, *requirement);
+        }
+        if let Some(requirement) = self.alignment_fn.as_ref().and_then(|f| f(name, data)) {
+            if !is_valid_alignment(requirement) {
+                return Err(SarcWriteError::InvalidAlignmentError(requirement));
+            }
+            return Ok(num::integer::lcm(alignment, requirement));
+        }
+        let ext = split_file_name(name).1.unwrap_or("");
+        if let Some(requirement) = self.alignment_map.get(ext).or_else(|| {
+            self.use_default_alignments
+                .then(|| get_default_alignments(self.endian).get(ext))
+                .flatten()
+        }) {
             alignment = num::integer::lcm(alignment, *requirement);
         }
         if self.legacy && Self::is_file_sarc(data) {
             alignment = num::integer::lcm(alignment, 0x2000);
         }
-        if self.legacy || !get_botw_factory_names().contains(ext) {
+        if self.use_default_alignments && (self.legacy || !get_botw_factory_names().contains(ext)) {
             alignment = num::integer::lcm(alignment, Self::get_alignment_for_new_binary_file(data));
             if let Endian::Big = self.endian {
                 alignment = num::integer::lcm(alignment, Self::get_alignment_for_cafe_bflim(data));
             }
         }
-        alignment
+        Ok(alignment)
     }
 }
 #[cfg(test)]
 mod tests {
-    use crate::{Sarc, SarcWriter};
+    use crate::{CompressionLevel, Endian, Sarc, SarcWriter};
 
     #[test]
     fn make_sarc() {
@@ -376,4 +1067,311 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn yaz0_roundtrip() {
+        let data = std::fs::read("test/ActorRecreateTag.bactorpack").unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        for level in [CompressionLevel::Fast, CompressionLevel::Max] {
+            let compressed = SarcWriter::from_sarc(&sarc)
+                .write_to_bytes_compressed(level)
+                .unwrap();
+            assert!(SarcWriter::is_file_sarc(&compressed));
+            let new_sarc = Sarc::new(&compressed).unwrap();
+            assert!(Sarc::are_files_equal(&sarc, &new_sarc));
+        }
+    }
+
+    #[test]
+    fn deterministic_order() {
+        // "aaseqa.bin" and "bxaaac.bin" have the same hash with the default multiplier
+        let files = [
+            ("aaseqa.bin", &b"first"[..]),
+            ("bxaaac.bin", &b"second"[..]),
+            ("", &b"nameless"[..]),
+        ];
+        let mut forward = SarcWriter::new(Endian::Little);
+        for (name, data) in files.iter() {
+            forward.files.insert(name.to_string(), data.to_vec().into());
+        }
+        let mut backward = SarcWriter::new(Endian::Little);
+        for (name, data) in files.iter().rev() {
+            backward
+                .files
+                .insert(name.to_string(), data.to_vec().into());
+        }
+        assert_eq!(
+            forward.write_to_bytes().unwrap(),
+            backward.write_to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn dedup_shares_data() {
+        let mut writer = SarcWriter::new(Endian::Little);
+        writer.add_file("A.bin", b"shared".to_vec());
+        writer.add_file("B.bin", b"unique".to_vec());
+        writer.add_file("C.bin", b"shared".to_vec());
+        let plain = writer.write_to_bytes().unwrap();
+        writer.set_dedup(true);
+        let deduped = writer.write_to_bytes().unwrap();
+        assert!(deduped.len() < plain.len());
+        let sarc = Sarc::new(&deduped).unwrap();
+        assert_eq!(sarc.get_file("A.bin").unwrap().unwrap().data, b"shared");
+        assert_eq!(sarc.get_file("B.bin").unwrap().unwrap().data, b"unique");
+        assert_eq!(sarc.get_file("C.bin").unwrap().unwrap().data, b"shared");
+    }
+
+    #[test]
+    fn convert_endian() {
+        let mut writer = SarcWriter::new(Endian::Big);
+        writer.add_file("Font/Test.bffnt", vec![0; 0x10]);
+        let big = writer.write_to_bytes().unwrap();
+        assert_eq!(Sarc::new(&big).unwrap().alignment_of(0).unwrap(), 0x2000);
+        writer.set_endian(Endian::Little);
+        let little = writer.write_to_bytes().unwrap();
+        let sarc = Sarc::new(&little).unwrap();
+        assert_eq!(sarc.endian(), Endian::Little);
+        assert_eq!(sarc.alignment_of(0).unwrap(), 0x1000);
+    }
+
+    #[test]
+    fn override_default_alignment() {
+        let mut writer = SarcWriter::new(Endian::Little);
+        writer.add_file("Font/Test.bffnt", vec![0; 0x10]);
+        writer
+            .add_alignment_requirement("bffnt".to_owned(), 0x100)
+            .unwrap();
+        let data = writer.write_to_bytes().unwrap();
+        assert_eq!(Sarc::new(&data).unwrap().alignment_of(0).unwrap(), 0x100);
+    }
+
+    #[test]
+    fn write_streaming() {
+        let data = std::fs::read("test/Dungeon119.pack").unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        let mut writer = SarcWriter::from_sarc(&sarc);
+        let mut streamed = vec![];
+        writer.write_streaming(&mut streamed).unwrap();
+        assert_eq!(streamed, writer.write_to_bytes().unwrap());
+    }
+
+    #[test]
+    fn from_sarc_shared() {
+        fn load(data: &[u8]) -> SarcWriter<'static> {
+            // The archive is dropped, so the writer must own the data
+            SarcWriter::from_sarc(&Sarc::new(data).unwrap())
+        }
+        let data = std::fs::read("test/Dungeon119.pack").unwrap();
+        let owned = load(&data);
+        assert!(owned
+            .files
+            .values()
+            .all(|data| matches!(data, std::borrow::Cow::Owned(_))));
+        let sarc = Sarc::new(&data).unwrap();
+        let mut shared = SarcWriter::from_sarc_shared(&sarc);
+        assert!(shared
+            .files
+            .values()
+            .all(|data| matches!(data, std::borrow::Cow::Borrowed(_))));
+        assert_eq!(shared, owned);
+        assert_eq!(shared.write_to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn append_to() {
+        let mut writer = SarcWriter::new(Endian::Little);
+        writer.set_hash_multiplier(0x1F).unwrap();
+        writer.add_file("a.bin", b"first".to_vec());
+        let data = writer.write_to_bytes().unwrap();
+        let base = Sarc::new(&data).unwrap();
+        let appended = SarcWriter::append_to(&base)
+            .with_file("b.bin", b"second".to_vec())
+            .write_to_bytes()
+            .unwrap();
+        let sarc = Sarc::new(&appended).unwrap();
+        assert_eq!(sarc.hash_multiplier(), 0x1F);
+        assert_eq!(sarc.guess_hash_multiplier(), Some(0x1F));
+        assert_eq!(sarc.get_file("a.bin").unwrap().unwrap().data, b"first");
+        assert_eq!(sarc.get_file("b.bin").unwrap().unwrap().data, b"second");
+    }
+
+    #[test]
+    fn custom_version() {
+        let mut writer = SarcWriter::new(Endian::Big);
+        writer.set_version(0x0200);
+        let data = writer.write_to_bytes().unwrap();
+        assert!(Sarc::new(&data).is_err());
+        let sarc = Sarc::new_any_version(&data).unwrap();
+        assert_eq!(sarc.version(), 0x0200);
+        assert_eq!(SarcWriter::from_sarc(&sarc).write_to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn padding_byte() {
+        let mut writer = SarcWriter::new(Endian::Little)
+            .with_file("a.bin", b"data".to_vec())
+            .with_min_alignment(0x100)
+            .unwrap();
+        writer.set_padding_byte(0xFF);
+        let data = writer.write_to_bytes().unwrap();
+        // The name is padded with zeros up to the end of the name table at 0x40
+        assert_eq!(&data[0x38..0x40], b"a.bin\0\0\0");
+        assert!(data[0x40..0x100].iter().all(|b| *b == 0xFF));
+        let sarc = Sarc::new(&data).unwrap();
+        assert_eq!(sarc.get_file("a.bin").unwrap().unwrap().data, b"data");
+    }
+
+    #[test]
+    fn padding_byte_roundtrip() {
+        // ksky files are aligned to 8 by default, leaving gaps between them
+        let mut writer = SarcWriter::new(Endian::Big)
+            .with_file("a.ksky", b"abc".to_vec())
+            .with_file("b.ksky", b"de".to_vec());
+        writer.set_padding_byte(0xFF);
+        let data = writer.write_to_bytes().unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        assert_eq!(sarc.guess_padding_byte(), Some(0xFF));
+        assert_eq!(SarcWriter::from_sarc(&sarc).write_to_bytes().unwrap(), data);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_invalid_settings() {
+        for (field, value) in [
+            ("min_alignment", 0),
+            ("name_alignment", 2),
+            ("file_size_alignment", 3),
+            ("hash_multiplier", 0),
+        ] {
+            let mut json = serde_json::to_value(SarcWriter::new(Endian::Little)).unwrap();
+            json[field] = value.into();
+            let mut writer: SarcWriter = serde_json::from_value(json).unwrap();
+            assert!(writer.write_to_bytes().is_err(), "{} = {}", field, value);
+        }
+    }
+
+    #[test]
+    fn from_directory() {
+        let dir = std::env::temp_dir().join(format!("sarc-from-directory-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Dir/Sub")).unwrap();
+        std::fs::create_dir_all(dir.join("Empty")).unwrap();
+        std::fs::write(dir.join("a.bin"), b"top").unwrap();
+        std::fs::write(dir.join("Dir/Sub/b.bin"), b"nested").unwrap();
+        let writer = SarcWriter::from_directory(&dir, Endian::Little).unwrap();
+        let mut files: Vec<_> = writer.files().collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [("Dir/Sub/b.bin", &b"nested"[..]), ("a.bin", &b"top"[..])]
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("a.bin"), dir.join("Dir/link.bin")).unwrap();
+            assert!(matches!(
+                SarcWriter::from_directory(&dir, Endian::Little),
+                Err(super::SarcWriteError::SymlinkError(path)) if path == dir.join("Dir/link.bin")
+            ));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn name_alignment() {
+        let names = ["a.bin", "Dir/bb.bin", "Dir/Sub/ccc.bin", "dddd.bin"];
+        for alignment in [8, 16] {
+            let mut writer = SarcWriter::new(Endian::Little);
+            writer.set_name_alignment(alignment).unwrap();
+            for name in names {
+                writer.add_file(name, name.as_bytes().to_vec());
+            }
+            let data = writer.write_to_bytes().unwrap();
+            let sarc = Sarc::new_validated(&data).unwrap();
+            for name in names {
+                assert_eq!(sarc.get_file(name).unwrap().unwrap().data, name.as_bytes());
+            }
+            for i in 0..names.len() {
+                let pos = 0x14 + 0xC + 0x10 * i + 4;
+                let offset = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+                assert_eq!((offset & 0xFFFFFF) as usize * 4 % alignment, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn file_size_alignment() {
+        let mut writer = SarcWriter::new(Endian::Little).with_file("a.bin", b"data".to_vec());
+        let unaligned = writer.write_to_bytes().unwrap();
+        writer.set_file_size_alignment(0x1000).unwrap();
+        let data = writer.write_to_bytes().unwrap();
+        assert_eq!(data.len(), 0x1000);
+        // Only the file size in the header and the trailing padding differ
+        assert_eq!(data[0xC..unaligned.len()], unaligned[0xC..]);
+        let sarc = Sarc::new_validated(&data).unwrap();
+        assert_eq!(sarc.loaded_size(), 0x1000);
+        assert_eq!(sarc.get_file("a.bin").unwrap().unwrap().data, b"data");
+    }
+
+    #[test]
+    fn unsorted() {
+        let names = ["a.bin", "b.bin", "c.bin", "d.bin"];
+        let mut writer = SarcWriter::new(Endian::Little);
+        for name in names {
+            writer.add_file(name, name.as_bytes().to_vec());
+        }
+        let sorted_data = writer.write_to_bytes().unwrap();
+        let sorted = Sarc::new(&sorted_data).unwrap();
+        let mut order: Vec<_> = sorted.files().map(|file| file.name.unwrap()).collect();
+        order.reverse();
+
+        let mut writer = SarcWriter::new(Endian::Little);
+        writer.set_sort(false);
+        for name in &order {
+            writer.add_file(*name, name.as_bytes().to_vec());
+        }
+        let data = writer.write_to_bytes().unwrap();
+        let sarc = Sarc::new(&data).unwrap();
+        let written: Vec<_> = sarc.files().map(|file| file.name.unwrap()).collect();
+        assert_eq!(written, order);
+        for name in names {
+            assert_eq!(
+                sarc.get_file_linear(name).unwrap().unwrap().data,
+                name.as_bytes()
+            );
+        }
+        assert!(Sarc::new_validated(&data).is_err());
+    }
+
+    #[test]
+    fn too_many_files() {
+        let mut writer = SarcWriter::new(Endian::Little);
+        for i in 0..0x3FFF {
+            writer.add_file(format!("{}.bin", i), vec![]);
+        }
+        let data = writer.write_to_bytes().unwrap();
+        assert_eq!(Sarc::new(&data).unwrap().file_count(), 0x3FFF);
+        writer.add_file("0x4000.bin", vec![]);
+        assert!(matches!(
+            writer.write_to_bytes(),
+            Err(super::SarcWriteError::TooManyFiles(0x4000))
+        ));
+    }
+
+    #[test]
+    fn empty_archive() {
+        for endian in [Endian::Big, Endian::Little] {
+            let data = SarcWriter::new(endian).write_to_bytes().unwrap();
+            assert_eq!(data.len(), 0x28);
+            let sarc = Sarc::new_validated(&data).unwrap();
+            assert_eq!(sarc.file_count(), 0);
+            assert_eq!(sarc.files().count(), 0);
+            assert!(sarc.get_file("missing.bin").unwrap().is_none());
+            assert!(sarc.file_at(0).is_err());
+            assert_eq!(sarc.guess_min_alignment().unwrap(), 4);
+            let mut writer = SarcWriter::from_sarc(&sarc);
+            assert!(writer.is_empty());
+            assert_eq!(writer.write_to_bytes().unwrap(), data);
+        }
+    }
 }
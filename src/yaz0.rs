@@ -0,0 +1,163 @@
+//! Minimal Yaz0 support for compressed archives (e.g. BOTW's `.pack` and
+//! `.sbactorpack` files).
+use alloc::vec::Vec;
+
+pub(crate) fn is_yaz0(data: &[u8]) -> bool {
+    data.len() >= 0x10 && &data[0..4] == b"Yaz0"
+}
+
+/// Decompresses a Yaz0 stream, including its header. Returns `None` if the data
+/// is not Yaz0 or is truncated or corrupt.
+pub(crate) fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    if !is_yaz0(data) {
+        return None;
+    }
+    let size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    // The size is untrusted, so don't allocate more than the data can expand
+    // to: every input byte yields at most 0x111 bytes (a 3 byte back-reference
+    // of maximum length)
+    if size > data.len().saturating_mul(0x111) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(size);
+    let mut src = 0x10;
+    let mut group_header = 0u8;
+    let mut bits_left = 0;
+    while out.len() < size {
+        if bits_left == 0 {
+            group_header = *data.get(src)?;
+            src += 1;
+            bits_left = 8;
+        }
+        if group_header & 0x80 != 0 {
+            out.push(*data.get(src)?);
+            src += 1;
+        } else {
+            let b1 = *data.get(src)? as usize;
+            let b2 = *data.get(src + 1)? as usize;
+            src += 2;
+            let distance = (((b1 & 0xF) << 8) | b2) + 1;
+            let length = match b1 >> 4 {
+                0 => {
+                    let b3 = *data.get(src)? as usize;
+                    src += 1;
+                    b3 + 0x12
+                }
+                n => n + 2,
+            };
+            let start = out.len().checked_sub(distance)?;
+            for i in start..start + length {
+                if out.len() == size {
+                    break;
+                }
+                out.push(out[i]);
+            }
+        }
+        group_header <<= 1;
+        bits_left -= 1;
+    }
+    Some(out)
+}
+
+/// How much effort to spend looking for repeated data when compressing with Yaz0
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CompressionLevel {
+    /// Only check a few recent candidates for each match
+    Fast,
+    /// Check every candidate in the window for the longest match
+    Max,
+}
+
+#[cfg(feature = "std")]
+const WINDOW_SIZE: usize = 0x1000;
+#[cfg(feature = "std")]
+const MIN_MATCH: usize = 3;
+#[cfg(feature = "std")]
+const MAX_MATCH: usize = 0x111;
+#[cfg(feature = "std")]
+const HASH_SIZE: usize = 1 << 15;
+#[cfg(feature = "std")]
+const NO_POS: usize = usize::MAX;
+
+#[cfg(feature = "std")]
+#[inline(always)]
+fn hash3(data: &[u8], pos: usize) -> usize {
+    (((data[pos] as usize) << 10) ^ ((data[pos + 1] as usize) << 5) ^ data[pos + 2] as usize)
+        & (HASH_SIZE - 1)
+}
+
+/// Compresses data as a Yaz0 stream, including the 0x10 byte header
+#[cfg(feature = "std")]
+pub(crate) fn compress(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+    let max_chain = match level {
+        CompressionLevel::Fast => 16,
+        CompressionLevel::Max => WINDOW_SIZE,
+    };
+    let mut out = Vec::with_capacity(0x10 + data.len() + data.len() / 8 + 1);
+    out.extend_from_slice(b"Yaz0");
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&[0; 8]);
+
+    // Hash chains of previous positions starting with the same 3 bytes. Only
+    // positions inside the window are ever followed, so a ring buffer is enough.
+    let mut head = vec![NO_POS; HASH_SIZE];
+    let mut prev = vec![NO_POS; WINDOW_SIZE];
+    let mut inserted = 0;
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let group_header_pos = out.len();
+        out.push(0);
+        for bit in 0..8 {
+            if pos >= data.len() {
+                break;
+            }
+            while inserted < pos && inserted + MIN_MATCH <= data.len() {
+                let hash = hash3(data, inserted);
+                prev[inserted % WINDOW_SIZE] = head[hash];
+                head[hash] = inserted;
+                inserted += 1;
+            }
+
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut best_length = 0;
+            let mut best_distance = 0;
+            if max_length >= MIN_MATCH {
+                let mut candidate = head[hash3(data, pos)];
+                let mut chain = 0;
+                while candidate != NO_POS && pos - candidate <= WINDOW_SIZE && chain < max_chain {
+                    let length = (0..max_length)
+                        .take_while(|i| data[candidate + i] == data[pos + i])
+                        .count();
+                    if length > best_length {
+                        best_length = length;
+                        best_distance = pos - candidate;
+                        if length == max_length {
+                            break;
+                        }
+                    }
+                    candidate = prev[candidate % WINDOW_SIZE];
+                    chain += 1;
+                }
+            }
+
+            if best_length >= MIN_MATCH {
+                let distance = best_distance - 1;
+                if best_length < 0x12 {
+                    out.push((((best_length - 2) << 4) | (distance >> 8)) as u8);
+                    out.push((distance & 0xFF) as u8);
+                } else {
+                    out.push((distance >> 8) as u8);
+                    out.push((distance & 0xFF) as u8);
+                    out.push((best_length - 0x12) as u8);
+                }
+                pos += best_length;
+            } else {
+                out[group_header_pos] |= 0x80 >> bit;
+                out.push(data[pos]);
+                pos += 1;
+            }
+        }
+    }
+    out
+}